@@ -0,0 +1,44 @@
+#!/usr/bin/env bash
+# Deploys a papr controller on a mainnet fork and dumps the node's state to
+# src/fixtures for the local node tests, see src/local_node.rs. Commit the
+# state and addresses it writes so the tests run offline.
+#
+#   FORK_URL=https://.. PAPR_CONTRACTS=../papr scripts/anvil-state.sh
+#
+# PAPR_CONTRACTS is a checkout of the papr contracts. The controller creates its
+# Uniswap pool on deploy, so the node forks mainnet. Needs foundry and jq.
+set -euo pipefail
+
+: "${FORK_URL:?FORK_URL must be a mainnet RPC url}"
+: "${PAPR_CONTRACTS:?PAPR_CONTRACTS must be a checkout of the papr contracts}"
+FORK_BLOCK="${FORK_BLOCK:-16508300}"
+PORT="${PORT:-8546}"
+
+cd "$(dirname "$0")/.."
+FIXTURES="$PWD/src/fixtures"
+RPC="http://127.0.0.1:$PORT"
+# anvil account 0, the controller's oracle signer
+KEY="0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+ORACLE_SIGNER="0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+WETH="0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+MAX_LTV="500000000000000000"
+
+anvil --fork-url "$FORK_URL" --fork-block-number "$FORK_BLOCK" --port "$PORT" \
+    --dump-state "$FIXTURES/anvil_state.json" --silent &
+ANVIL=$!
+trap 'kill $ANVIL 2>/dev/null || true' EXIT
+until cast block-number --rpc-url "$RPC" >/dev/null 2>&1; do sleep 1; done
+
+collateral=$(forge create scripts/MintableERC721.sol:MintableERC721 \
+    --rpc-url "$RPC" --private-key "$KEY" --json | jq -r .deployedTo)
+controller=$(cd "$PAPR_CONTRACTS" && forge create src/PaprController.sol:PaprController \
+    --rpc-url "$RPC" --private-key "$KEY" --json \
+    --constructor-args "papr local" "paprLOCAL" "$MAX_LTV" "$WETH" "$ORACLE_SIGNER" "[$collateral]" |
+    jq -r .deployedTo)
+
+# anvil writes the state dump when interrupted
+kill -INT $ANVIL
+wait $ANVIL || true
+jq -n --arg controller "$controller" --arg collateral "$collateral" \
+    '{controller: $controller, collateral: $collateral}' >"$FIXTURES/anvil_addresses.json"
+echo "controller $controller collateral $collateral"
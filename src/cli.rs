@@ -0,0 +1,145 @@
+use crate::{reservoir::oracle::PriceKind, vault_source::VaultSourceKind, watch::ReportFormat};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+pub const USDC: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+#[derive(Parser)]
+#[command(
+    name = "auction-bot",
+    about = "Starts and purchases papr liquidation auctions"
+)]
+pub struct Cli {
+    /// Controller config for the network the bot runs against
+    #[arg(
+        long,
+        global = true,
+        env = "CONFIG_PATH",
+        default_value = "config/mainnet.toml"
+    )]
+    pub config: PathBuf,
+    /// Log of attempted liquidation auctions
+    #[arg(
+        long,
+        global = true,
+        env = "JOURNAL_PATH",
+        default_value = "data/auctions.jsonl"
+    )]
+    pub journal: PathBuf,
+    /// Where liquidation scanning finds vaults
+    #[arg(
+        long,
+        global = true,
+        env = "VAULT_SOURCE",
+        value_enum,
+        default_value_t = VaultSourceKind::Subgraph
+    )]
+    pub vault_source: VaultSourceKind,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start liquidation auctions for liquidatable vaults of whitelisted controllers
+    Liquidate {
+        /// Keep running, checking for liquidatable vaults every `interval` seconds
+        #[arg(long, env = "DAEMON_MODE")]
+        daemon: bool,
+        /// Seconds to wait between runs in daemon mode
+        #[arg(long, env = "POLL_INTERVAL_SECONDS", default_value_t = 60)]
+        interval: u64,
+        /// Find liquidatable vaults but do not send any transactions
+        #[arg(long, env = "DISABLE_EXECUTE_START_ACTION")]
+        dry_run: bool,
+    },
+    /// Purchase ongoing liquidation auctions from whitelisted controllers
+    Purchase {
+        /// Send transactions for profitable auctions, otherwise only report them
+        #[arg(long, env = "EXECUTE_PURCHASES")]
+        execute: bool,
+    },
+    /// List liquidatable vaults of whitelisted controllers without starting auctions
+    Scan,
+    /// List vaults the target's drift makes liquidatable soon, soonest first
+    Forecast {
+        /// Seconds ahead to forecast
+        #[arg(long, default_value_t = 24 * 60 * 60)]
+        horizon: u64,
+    },
+    /// List vaults close to their max debt, closest to liquidation first
+    Watch {
+        /// Fraction of the max debt to report vaults within, e.g. 0.1 for 10%
+        #[arg(long, default_value_t = 0.1)]
+        within: f64,
+        /// Text to read, or JSON or CSV for the risk dashboard
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+        /// Write the report to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the liquidation auctions the bot has attempted and the auctions it
+    /// evaluated for purchase
+    History {
+        /// Only show entries for this controller
+        #[arg(long)]
+        controller: Option<String>,
+        /// Only show attempts for this vault account
+        #[arg(long)]
+        vault: Option<String>,
+        /// Only show the most recent entries
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Print the Reservoir oracle top bid for a collection
+    Price {
+        collection: String,
+        /// Quote currency of the price
+        #[arg(long, default_value = USDC)]
+        currency: String,
+        #[arg(long, value_enum, default_value_t = PriceKind::Twap)]
+        kind: PriceKind,
+        /// Twap window in seconds, Reservoir's default if omitted
+        #[arg(long)]
+        twap_seconds: Option<u32>,
+    },
+    /// Count the bids on a collection that are at least `percent` of the top bid
+    BidsDepth {
+        collection: String,
+        /// Fraction of the top bid, e.g. 0.9 for 90%
+        percent: f64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{Cli, Command};
+    use clap::{CommandFactory, Parser};
+
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn bids_depth_parses_collection_and_percent() {
+        let cli = Cli::try_parse_from([
+            "auction-bot",
+            "bids-depth",
+            "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d",
+            "0.9",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::BidsDepth {
+                collection,
+                percent,
+            } => {
+                assert_eq!(collection, "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d");
+                assert_eq!(percent, 0.9);
+            }
+            _ => panic!("expected bids-depth"),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use ethers::types::Address;
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path};
+
+const SEVEN_DAYS_SECONDS: u32 = 604800;
+const TWO_DAYS_SECONDS: u64 = 172800;
+const DEFAULT_MAX_SUBGRAPH_LAG_BLOCKS: u64 = 10;
+const DEFAULT_SCAN_CONCURRENCY: usize = 4;
+
+/// Controllers the bot is allowed to act on, loaded from a per network TOML file
+/// such as `config/mainnet.toml`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// refuse to act when the subgraph is further than this behind the chain head
+    #[serde(default = "default_max_subgraph_lag_blocks")]
+    pub max_subgraph_lag_blocks: u64,
+    /// collateral scanned at once, across every controller
+    #[serde(default = "default_scan_concurrency")]
+    pub scan_concurrency: usize,
+    pub controllers: Vec<ControllerConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ControllerConfig {
+    pub address: String,
+    pub name: Option<String>,
+    /// start liquidation auctions for this controller's vaults
+    #[serde(default)]
+    pub liquidate: bool,
+    /// purchase this controller's ongoing liquidation auctions
+    #[serde(default)]
+    pub purchase: bool,
+    /// twap window used when fetching oracle prices
+    #[serde(default = "default_twap_seconds")]
+    pub twap_seconds: u32,
+    /// vaults with an auction started more recently than this are skipped
+    #[serde(default = "default_auction_spacing_seconds")]
+    pub auction_spacing_seconds: u64,
+    /// collateral to act on, all of the controller's allowed collateral if omitted
+    pub collateral: Option<Vec<String>>,
+    /// where replaying the controller's events starts when finding vaults on
+    /// chain, required by the chain vault source
+    pub deployed_at_block: Option<u64>,
+}
+
+fn default_max_subgraph_lag_blocks() -> u64 {
+    DEFAULT_MAX_SUBGRAPH_LAG_BLOCKS
+}
+
+fn default_scan_concurrency() -> usize {
+    DEFAULT_SCAN_CONCURRENCY
+}
+
+fn default_twap_seconds() -> u32 {
+    SEVEN_DAYS_SECONDS
+}
+
+fn default_auction_spacing_seconds() -> u64 {
+    TWO_DAYS_SECONDS
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, eyre::Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| eyre::eyre!("could not read config {}: {}", path.display(), e))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, eyre::Error> {
+        let mut config: Config = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks addresses and lowercases them so they match subgraph ids.
+    fn validate(&mut self) -> Result<(), eyre::Error> {
+        if self.scan_concurrency == 0 {
+            return Err(eyre::eyre!("scan_concurrency must be greater than 0"));
+        }
+        let mut seen = HashSet::new();
+        for controller in self.controllers.iter_mut() {
+            controller.address = normalize_address(&controller.address)?;
+            if !seen.insert(controller.address.clone()) {
+                return Err(eyre::eyre!(
+                    "controller {} configured more than once",
+                    controller.address
+                ));
+            }
+            if controller.twap_seconds == 0 {
+                return Err(eyre::eyre!(
+                    "controller {} twap_seconds must be greater than 0",
+                    controller.address
+                ));
+            }
+            if let Some(collateral) = controller.collateral.as_mut() {
+                for addr in collateral.iter_mut() {
+                    *addr = normalize_address(addr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn liquidation_controllers(&self) -> impl Iterator<Item = &ControllerConfig> {
+        self.controllers.iter().filter(|c| c.liquidate)
+    }
+
+    pub fn purchase_controllers(&self) -> impl Iterator<Item = &ControllerConfig> {
+        self.controllers.iter().filter(|c| c.purchase)
+    }
+}
+
+impl ControllerConfig {
+    pub fn collateral_enabled(&self, collateral: &str) -> bool {
+        match &self.collateral {
+            Some(enabled) => enabled.contains(&collateral.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+fn normalize_address(addr: &str) -> Result<String, eyre::Error> {
+    addr.parse::<Address>()
+        .map_err(|_| eyre::eyre!("invalid address {} in config", addr))?;
+    Ok(addr.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    #[test]
+    fn parse_applies_defaults_and_lowercases_addresses() {
+        let config = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3B29C19FF2FCEA0FF98D0EF5B184354D74EA74B0"
+            liquidate = true
+            collateral = ["0xBC4CA0EDA7647A8AB7C2061C2E118A18A936F13D"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.max_subgraph_lag_blocks, 10);
+        assert_eq!(config.scan_concurrency, 4);
+        let controller = config.liquidation_controllers().next().unwrap();
+        assert_eq!(
+            controller.address,
+            "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+        );
+        assert_eq!(controller.deployed_at_block, None);
+        assert_eq!(controller.twap_seconds, 604800);
+        assert_eq!(controller.auction_spacing_seconds, 172800);
+        assert!(controller.collateral_enabled("0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d"));
+        assert!(!controller.collateral_enabled("0x79ab709dadc05cd2c0f7322bc7e3d70d2550942c"));
+        assert!(config.purchase_controllers().next().is_none());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_address() {
+        let result = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x1234"
+            "#,
+        );
+        assert_eq!(
+            "invalid address 0x1234 in config",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_zero_scan_concurrency() {
+        let result = Config::parse(
+            r#"
+            scan_concurrency = 0
+
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            "#,
+        );
+        assert_eq!(
+            "scan_concurrency must be greater than 0",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_duplicate_controllers() {
+        let result = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+
+            [[controllers]]
+            address = "0x3B29C19FF2FCEA0FF98D0EF5B184354D74EA74B0"
+            "#,
+        );
+        assert_eq!(
+            "controller 0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0 configured more than once",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn shipped_configs_are_valid() {
+        Config::load("config/mainnet.toml").unwrap();
+        Config::load("config/goerli.toml").unwrap();
+    }
+}
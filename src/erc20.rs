@@ -0,0 +1,41 @@
+use crate::papr_controller::Client;
+use ethers::{
+    prelude::abigen,
+    types::{Address, U256},
+};
+use std::sync::Arc;
+
+abigen!(ERC20, "src/abis/ERC20.json");
+
+/// Approves `spender` for `amount` of `token` unless it is already allowed to
+/// spend that much of `client`'s balance.
+pub async fn ensure_allowance(
+    client: &Arc<Client>,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<(), eyre::Error> {
+    let erc20 = ERC20::new(token, Arc::clone(client));
+    let allowance = erc20.allowance(client.address(), spender).call().await?;
+    if allowance >= amount {
+        return Ok(());
+    }
+    erc20
+        .approve(spender, amount)
+        .send()
+        .await?
+        .await?
+        .ok_or(eyre::eyre!("approve no transaction receipt"))?;
+    Ok(())
+}
+
+pub async fn balance_of(
+    client: &Arc<Client>,
+    token: Address,
+    account: Address,
+) -> Result<U256, eyre::Error> {
+    Ok(ERC20::new(token, Arc::clone(client))
+        .balance_of(account)
+        .call()
+        .await?)
+}
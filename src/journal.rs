@@ -0,0 +1,320 @@
+use ethers::types::{Bytes, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// the simulation reverted so no transaction was sent
+    Skipped,
+    /// the transaction was broadcast, a later record with its hash settles it
+    Sent,
+    /// the transaction was mined and the auction started
+    Started,
+    /// the transaction failed to send, reverted on chain or lost its nonce
+    Failed,
+}
+
+/// One attempt to start a liquidation auction.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuctionAttempt {
+    pub timestamp: u64,
+    pub controller: String,
+    pub vault: String,
+    pub collateral: String,
+    pub token_id: U256,
+    pub oracle_price: U256,
+    pub tx_hash: Option<H256>,
+    #[serde(default)]
+    pub nonce: Option<U256>,
+    /// signed transaction of a `Sent` record, to rebroadcast or replace it
+    #[serde(default)]
+    pub raw_tx: Option<Bytes>,
+    pub gas_used: Option<U256>,
+    /// id of the auction the transaction started
+    #[serde(default)]
+    pub auction_id: Option<U256>,
+    /// block the vault source found the vault liquidatable at
+    #[serde(default, alias = "subgraph_block")]
+    pub snapshot_block: Option<u64>,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// no bid in the underlying to sell the NFT into
+    NoBid,
+    /// the bid does not cover the papr and gas
+    Unprofitable,
+    /// profitable but purchases are not executed
+    Profitable,
+    /// the auction was purchased and the NFT sold
+    Purchased,
+    /// evaluating or purchasing the auction failed
+    Failed,
+}
+
+/// One evaluation of an ongoing auction for purchase. Amounts other than the
+/// papr price are in the controller's underlying.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PurchaseDecision {
+    pub timestamp: u64,
+    pub controller: String,
+    pub auction_id: U256,
+    pub collection: String,
+    pub token_id: U256,
+    /// block the auction was read at
+    pub snapshot_block: u64,
+    /// papr price after any auction creator discount
+    pub price: Option<U256>,
+    pub cost: Option<U256>,
+    pub proceeds: Option<U256>,
+    pub gas_cost: Option<U256>,
+    pub decision: Decision,
+    pub error: Option<String>,
+}
+
+/// A line of the journal
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Entry {
+    Auction(AuctionAttempt),
+    Purchase(PurchaseDecision),
+}
+
+/// Append only JSON lines log of the auctions the bot has attempted and
+/// evaluated for purchase, so we remember across runs which auctions we started.
+pub struct Journal {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl Journal {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, eyre::Error> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            path,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn record(&self, attempt: &AuctionAttempt) -> Result<(), eyre::Error> {
+        self.append(attempt)
+    }
+
+    pub fn record_purchase(&self, decision: &PurchaseDecision) -> Result<(), eyre::Error> {
+        self.append(decision)
+    }
+
+    fn append(&self, entry: &impl Serialize) -> Result<(), eyre::Error> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let _guard = self
+            .write_lock
+            .lock()
+            .map_err(|_| eyre::eyre!("journal lock poisoned"))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<Vec<Entry>, eyre::Error> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(entries)
+    }
+
+    pub fn attempts(&self) -> Result<Vec<AuctionAttempt>, eyre::Error> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Auction(attempt) => Some(attempt),
+                Entry::Purchase(_) => None,
+            })
+            .collect())
+    }
+
+    /// Transactions sent for `controller` that no later record settles, e.g.
+    /// because they were still unmined at the end of a pass.
+    pub fn unsettled(&self, controller: &str) -> Result<Vec<AuctionAttempt>, eyre::Error> {
+        let attempts = self.attempts()?;
+        Ok(attempts
+            .iter()
+            .enumerate()
+            .filter(|(i, a)| {
+                a.outcome == Outcome::Sent
+                    && a.controller.eq_ignore_ascii_case(controller)
+                    && !attempts[i + 1..]
+                        .iter()
+                        .any(|later| later.outcome != Outcome::Sent && later.tx_hash == a.tx_hash)
+            })
+            .map(|(_, a)| a.clone())
+            .collect())
+    }
+
+    /// Whether we started this auction, entitling us to the auction creator
+    /// discount if we also purchase it.
+    pub fn started_auction_id(&self, auction_id: U256) -> Result<bool, eyre::Error> {
+        Ok(self
+            .attempts()?
+            .iter()
+            .any(|a| a.outcome == Outcome::Started && a.auction_id == Some(auction_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::{AuctionAttempt, Decision, Entry, Journal, Outcome, PurchaseDecision};
+    use ethers::types::{Bytes, H256, U256};
+    use std::{env, fs, process};
+
+    #[test]
+    fn record_appends_attempts_that_can_be_read_back() {
+        let path = env::temp_dir().join(format!("auction-bot-journal-{}.jsonl", process::id()));
+        let _ = fs::remove_file(&path);
+        let journal = Journal::open(&path).unwrap();
+        assert!(journal.attempts().unwrap().is_empty());
+
+        let skipped = attempt(Outcome::Skipped, 1);
+        let started = AuctionAttempt {
+            auction_id: Some(U256::from(42)),
+            ..attempt(Outcome::Started, 2)
+        };
+        journal.record(&skipped).unwrap();
+        journal.record(&started).unwrap();
+
+        assert_eq!(journal.attempts().unwrap(), vec![skipped, started]);
+        assert!(journal.started_auction_id(U256::from(42)).unwrap());
+        assert!(!journal.started_auction_id(U256::from(43)).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn purchase_decisions_are_kept_apart_from_attempts() {
+        let path = env::temp_dir().join(format!(
+            "auction-bot-journal-purchase-{}.jsonl",
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let journal = Journal::open(&path).unwrap();
+        let decision = PurchaseDecision {
+            timestamp: 1674959723,
+            controller: "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0".to_string(),
+            auction_id: U256::from(42),
+            collection: "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d".to_string(),
+            token_id: U256::from(1),
+            snapshot_block: 16508300,
+            price: Some(U256::exp10(18)),
+            cost: Some(U256::exp10(18)),
+            proceeds: None,
+            gas_cost: None,
+            decision: Decision::NoBid,
+            error: None,
+        };
+        journal.record(&attempt(Outcome::Skipped, 1)).unwrap();
+        journal.record_purchase(&decision).unwrap();
+
+        assert_eq!(
+            journal.entries().unwrap(),
+            vec![
+                Entry::Auction(attempt(Outcome::Skipped, 1)),
+                Entry::Purchase(decision)
+            ]
+        );
+        assert_eq!(
+            journal.attempts().unwrap(),
+            vec![attempt(Outcome::Skipped, 1)]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_subgraph_block_of_older_journals() {
+        let mut line = serde_json::to_value(attempt(Outcome::Skipped, 1)).unwrap();
+        let fields = line.as_object_mut().unwrap();
+        let block = fields.remove("snapshot_block").unwrap();
+        fields.insert("subgraph_block".to_string(), block);
+        let attempt: AuctionAttempt = serde_json::from_value(line).unwrap();
+        assert_eq!(attempt.snapshot_block, Some(16508300));
+    }
+
+    #[test]
+    fn unsettled_lists_sent_attempts_without_an_outcome() {
+        let path = env::temp_dir().join(format!(
+            "auction-bot-journal-unsettled-{}.jsonl",
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let journal = Journal::open(&path).unwrap();
+        let sent = |token_id: u64| AuctionAttempt {
+            tx_hash: Some(H256::from_low_u64_be(token_id)),
+            nonce: Some(U256::from(token_id)),
+            raw_tx: Some(Bytes::from(vec![token_id as u8])),
+            ..attempt(Outcome::Sent, token_id)
+        };
+        journal.record(&sent(1)).unwrap();
+        journal.record(&sent(2)).unwrap();
+        journal
+            .record(&AuctionAttempt {
+                outcome: Outcome::Started,
+                ..sent(1)
+            })
+            .unwrap();
+
+        assert_eq!(
+            journal
+                .unsettled("0x3B29C19FF2FCEA0FF98D0EF5B184354D74EA74B0")
+                .unwrap(),
+            vec![sent(2)]
+        );
+        assert!(journal
+            .unsettled("0xd0a830278773282bbf635fd8e47b2447f1e9fe86")
+            .unwrap()
+            .is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn attempt(outcome: Outcome, token_id: u64) -> AuctionAttempt {
+        AuctionAttempt {
+            timestamp: 1674959723,
+            controller: "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0".to_string(),
+            vault: "0xbc3ed6b537f2980e66f396fe14210a56ba3f72c4".to_string(),
+            collateral: "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d".to_string(),
+            token_id: U256::from(token_id),
+            oracle_price: U256::exp10(18),
+            tx_hash: None,
+            nonce: None,
+            raw_tx: None,
+            gas_used: None,
+            auction_id: None,
+            snapshot_block: Some(16508300),
+            outcome,
+            error: None,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{future::Future, time::Duration};
+use tokio::signal;
+
+/// Runs `iteration` every `interval` until SIGINT or SIGTERM is received.
+/// An iteration in progress when the signal arrives is allowed to finish so we
+/// never abandon a transaction half way. Errors from an iteration are logged and
+/// the loop carries on with the next one. An iteration can ask for the next one
+/// to run sooner than `interval`.
+pub async fn run_until_shutdown<F, Fut>(interval: Duration, iteration: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<Duration>, eyre::Error>>,
+{
+    run_until(shutdown_signal(), interval, iteration).await
+}
+
+async fn run_until<F, Fut>(shutdown: impl Future<Output = ()>, interval: Duration, mut iteration: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Option<Duration>, eyre::Error>>,
+{
+    tokio::pin!(shutdown);
+    let mut shutting_down = false;
+
+    loop {
+        let current = iteration();
+        tokio::pin!(current);
+        let result = loop {
+            tokio::select! {
+                result = &mut current => break result,
+                _ = &mut shutdown, if !shutting_down => {
+                    println!("shutdown requested, finishing current iteration");
+                    shutting_down = true;
+                }
+            }
+        };
+        let delay = match result {
+            Ok(sooner) => sooner.map_or(interval, |sooner| sooner.min(interval)),
+            Err(err) => {
+                println!("iteration failed: {}", err);
+                interval
+            }
+        };
+        if shutting_down {
+            break;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut shutdown => break,
+        }
+    }
+    println!("shutting down");
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keeper::run_until;
+    use std::{sync::Mutex, time::Duration};
+    use tokio::time::{sleep, Instant};
+
+    #[tokio::test(start_paused = true)]
+    async fn carries_on_after_errors_and_runs_sooner_when_asked() {
+        let start = Instant::now();
+        let runs = Mutex::new(vec![]);
+        run_until(
+            sleep(Duration::from_secs(25)),
+            Duration::from_secs(10),
+            || {
+                let mut runs = runs.lock().unwrap();
+                runs.push(start.elapsed().as_secs());
+                let result = match runs.len() {
+                    1 => Err(eyre::eyre!("rpc down")),
+                    2 => Ok(Some(Duration::from_secs(2))),
+                    // later than the interval is clamped to it
+                    _ => Ok(Some(Duration::from_secs(60))),
+                };
+                async move { result }
+            },
+        )
+        .await;
+
+        assert_eq!(*runs.lock().unwrap(), vec![0, 10, 12, 22]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finishes_the_current_iteration_on_shutdown() {
+        let start = Instant::now();
+        let finished = Mutex::new(vec![]);
+        run_until(
+            sleep(Duration::from_secs(5)),
+            Duration::from_secs(1),
+            || async {
+                sleep(Duration::from_secs(10)).await;
+                finished.lock().unwrap().push(start.elapsed().as_secs());
+                Ok(None)
+            },
+        )
+        .await;
+
+        assert_eq!(*finished.lock().unwrap(), vec![10]);
+    }
+}
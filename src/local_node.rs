@@ -0,0 +1,195 @@
+//! An anvil node loaded with a papr deployment, for end-to-end tests against a
+//! real EVM. They are ignored by default, run them offline with
+//!
+//! ```text
+//! cargo test -- --ignored
+//! ```
+//!
+//! The node loads `src/fixtures/anvil_state.json`, an `anvil --dump-state` of a
+//! controller deployed with anvil account 0 as its oracle signer, allowing an
+//! ERC721 anyone can `mint`. The state and `src/fixtures/anvil_addresses.json`
+//! are committed so only anvil is needed. `scripts/anvil-state.sh` regenerates
+//! them, which needs a mainnet RPC and a papr contracts checkout.
+use crate::{
+    erc20::ERC20,
+    papr_controller::{Client, Collateral, PaprControllerABI},
+    reservoir::oracle::OracleMessage,
+    vault_source::Controller,
+};
+use ethers::{
+    abi::{encode, Token},
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, BlockNumber, U256},
+    utils::{keccak256, Anvil, AnvilInstance},
+};
+use serde::Deserialize;
+use std::{fs, sync::Arc};
+
+abigen!(
+    MintableERC721,
+    r#"[
+        function mint(address to, uint256 id) external
+        function setApprovalForAll(address operator, bool approved) external
+    ]"#
+);
+
+/// Seconds of the twap papr underwrites collateral with
+const TWAP_SECONDS: u64 = 7 * 24 * 60 * 60;
+/// `PriceKind.TWAP` in Reservoir's oracle contracts
+const TWAP_KIND: u8 = 1;
+
+const STATE_PATH: &str = "src/fixtures/anvil_state.json";
+const ADDRESSES_PATH: &str = "src/fixtures/anvil_addresses.json";
+
+/// Contracts deployed in the dumped state
+#[derive(Deserialize)]
+struct Deployment {
+    controller: Address,
+    collateral: Address,
+}
+
+pub struct LocalNode {
+    anvil: AnvilInstance,
+    pub controller: Address,
+    pub collateral: Address,
+}
+
+impl LocalNode {
+    /// Starts anvil from the dumped state. Panics if anvil is not installed.
+    pub fn spawn() -> Result<Self, eyre::Error> {
+        let missing = |path| {
+            eyre::eyre!(
+                "{} missing, generate and commit it with scripts/anvil-state.sh",
+                path
+            )
+        };
+        fs::metadata(STATE_PATH).map_err(|_| missing(STATE_PATH))?;
+        let addresses = fs::read_to_string(ADDRESSES_PATH).map_err(|_| missing(ADDRESSES_PATH))?;
+        let deployment: Deployment = serde_json::from_str(&addresses)?;
+        let anvil = Anvil::new().args(["--load-state", STATE_PATH]).spawn();
+        Ok(Self {
+            anvil,
+            controller: deployment.controller,
+            collateral: deployment.collateral,
+        })
+    }
+
+    /// Client signing as anvil account `account`
+    pub fn client(&self, account: usize) -> Arc<Client> {
+        let provider = Provider::<Http>::try_from(self.anvil.endpoint()).unwrap();
+        Arc::new(SignerMiddleware::new(provider, self.wallet(account)))
+    }
+
+    /// The controller's oracle signer
+    pub fn oracle_signer(&self) -> LocalWallet {
+        self.wallet(0)
+    }
+
+    fn wallet(&self, account: usize) -> LocalWallet {
+        LocalWallet::from(self.anvil.keys()[account].clone()).with_chain_id(self.anvil.chain_id())
+    }
+
+    pub async fn latest_timestamp(&self) -> Result<u64, eyre::Error> {
+        let block = self
+            .client(0)
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(eyre::eyre!("latest block not found"))?;
+        Ok(block.timestamp.as_u64())
+    }
+
+    pub async fn controller_params(&self) -> Result<Controller, eyre::Error> {
+        let contract = PaprControllerABI::new(self.controller, self.client(0));
+        let underlying = contract.underlying().call().await?;
+        Ok(Controller {
+            id: format!("{:?}", self.controller),
+            max_ltv: contract.max_ltv().call().await?,
+            underlying: format!("{:?}", underlying),
+            underlying_decimals: ERC20::new(underlying, self.client(0))
+                .decimals()
+                .call()
+                .await?,
+        })
+    }
+
+    /// Mints `token_id` to `borrower`, deposits it and borrows the max debt at
+    /// `price`, so any lower price makes the vault liquidatable.
+    pub async fn open_vault(
+        &self,
+        borrower: usize,
+        token_id: U256,
+        price: U256,
+    ) -> Result<(), eyre::Error> {
+        let client = self.client(borrower);
+        let account = client.address();
+        let nft = MintableERC721::new(self.collateral, Arc::clone(&client));
+        nft.mint(account, token_id).send().await?.await?;
+        nft.set_approval_for_all(self.controller, true)
+            .send()
+            .await?
+            .await?;
+
+        let controller = PaprControllerABI::new(self.controller, client);
+        controller
+            .add_collateral(vec![Collateral {
+                addr: self.collateral,
+                id: token_id,
+            }])
+            .send()
+            .await?
+            .await?;
+        let underlying = controller.underlying().call().await?;
+        let message = sign_price(
+            &self.oracle_signer(),
+            self.collateral,
+            underlying,
+            price,
+            self.latest_timestamp().await?,
+        );
+        let max_debt = controller.max_debt(price).call().await?;
+        controller
+            .increase_debt(
+                account,
+                self.collateral,
+                max_debt,
+                message.as_contract_oracle_info()?,
+            )
+            .send()
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+/// A Reservoir style twap top bid message for `collection`, priced in `currency`
+pub fn sign_price(
+    signer: &LocalWallet,
+    collection: Address,
+    currency: Address,
+    price: U256,
+    timestamp: u64,
+) -> OracleMessage {
+    let id = keccak256(encode(&[
+        Token::FixedBytes(
+            keccak256(
+                "ContractWideCollectionTopBidPrice(uint8 kind,uint256 twapSeconds,address contract)",
+            )
+            .to_vec(),
+        ),
+        Token::Uint(TWAP_KIND.into()),
+        Token::Uint(TWAP_SECONDS.into()),
+        Token::Address(collection),
+    ]));
+    let mut message = OracleMessage {
+        id: format!("0x{}", ethers::utils::hex::encode(id)),
+        payload: encode(&[Token::Address(currency), Token::Uint(price)]).into(),
+        timestamp,
+        signature: Default::default(),
+    };
+    let signature = signer.sign_hash(message.signing_hash().expect("id is 32 bytes"));
+    message.signature = signature.to_vec().into();
+    message
+}
@@ -1,28 +1,297 @@
+mod cli;
+mod config;
+mod erc20;
+mod journal;
+mod keeper;
+#[cfg(test)]
+mod local_node;
+#[cfg(test)]
+mod mock_server;
+mod nonce;
 mod papr_controller;
 mod papr_subgraph;
+mod projection;
 mod provider;
 mod purchase;
 mod reservoir;
 mod start;
+mod uniswap;
+mod vault_source;
+mod wad;
+mod watch;
 use crate::{
+    cli::{Cli, Command, USDC},
+    config::Config,
+    journal::{Entry, Journal},
+    keeper::run_until_shutdown,
+    papr_controller::PaprController,
     papr_subgraph::client::GraphQLClient,
-    reservoir::{client::ReservoirClient, oracle::PriceKind},
+    projection::{
+        prefetch_upcoming, watchlist_for_whitelisted_controllers, PrefetchedOracle, WatchlistEntry,
+    },
+    purchase::purchase_auctions_from_whitelisted_controllers,
+    reservoir::{
+        client::ReservoirClient,
+        oracle::{PriceKind, PriceOracle},
+    },
     start::start_liquidations_for_whitelisted_controllers,
+    vault_source::{chain::ChainVaultSource, VaultSource, VaultSourceKind},
+    watch::{render, watch_whitelisted_controllers},
 };
+use clap::Parser;
+use std::{fs, time::Duration};
 
 #[tokio::main]
 async fn main() -> Result<(), eyre::Error> {
+    let cli = Cli::parse();
     let graphql = GraphQLClient::default();
     let reservoir = ReservoirClient::default();
+    let journal = Journal::open(&cli.journal)?;
 
-    let x = start_liquidations_for_whitelisted_controllers(&reservoir, &graphql).await;
-    if let Some(err) = x.err() {
-        println!("{}", err);
+    match cli.command {
+        Command::Liquidate {
+            daemon,
+            interval,
+            dry_run,
+        } => {
+            let config = Config::load(&cli.config)?;
+            let interval = daemon.then(|| Duration::from_secs(interval));
+            match cli.vault_source {
+                VaultSourceKind::Subgraph => {
+                    liquidate(&reservoir, &graphql, &config, &journal, interval, dry_run).await
+                }
+                VaultSourceKind::Chain => {
+                    let vaults = ChainVaultSource::new(&config)?;
+                    liquidate(&reservoir, &vaults, &config, &journal, interval, dry_run).await
+                }
+            }
+        }
+        Command::Purchase { execute } => {
+            let config = Config::load(&cli.config)?;
+            purchase_auctions_from_whitelisted_controllers(
+                &reservoir,
+                &graphql,
+                PaprController::new,
+                &config,
+                &journal,
+                execute,
+            )
+            .await?;
+        }
+        Command::Scan => {
+            let config = Config::load(&cli.config)?;
+            match cli.vault_source {
+                VaultSourceKind::Subgraph => {
+                    start_liquidations_for_whitelisted_controllers(
+                        &reservoir,
+                        &graphql,
+                        PaprController::new,
+                        &config,
+                        &journal,
+                        true,
+                    )
+                    .await?
+                }
+                VaultSourceKind::Chain => {
+                    start_liquidations_for_whitelisted_controllers(
+                        &reservoir,
+                        &ChainVaultSource::new(&config)?,
+                        PaprController::new,
+                        &config,
+                        &journal,
+                        true,
+                    )
+                    .await?
+                }
+            }
+        }
+        Command::Forecast { horizon } => {
+            let config = Config::load(&cli.config)?;
+            let watchlist = match cli.vault_source {
+                VaultSourceKind::Subgraph => {
+                    watchlist_for_whitelisted_controllers(&reservoir, &graphql, &config, horizon)
+                        .await?
+                }
+                VaultSourceKind::Chain => {
+                    watchlist_for_whitelisted_controllers(
+                        &reservoir,
+                        &ChainVaultSource::new(&config)?,
+                        &config,
+                        horizon,
+                    )
+                    .await?
+                }
+            };
+            print_watchlist(&watchlist.entries);
+        }
+        Command::Watch {
+            within,
+            format,
+            output,
+        } => {
+            let config = Config::load(&cli.config)?;
+            let entries = match cli.vault_source {
+                VaultSourceKind::Subgraph => {
+                    watch_whitelisted_controllers(&reservoir, &graphql, &config, within).await?
+                }
+                VaultSourceKind::Chain => {
+                    watch_whitelisted_controllers(
+                        &reservoir,
+                        &ChainVaultSource::new(&config)?,
+                        &config,
+                        within,
+                    )
+                    .await?
+                }
+            };
+            let report = render(&entries, format)?;
+            match output {
+                Some(path) => fs::write(path, report)?,
+                None => print!("{}", report),
+            }
+        }
+        Command::History {
+            controller,
+            vault,
+            limit,
+        } => {
+            let entries: Vec<_> = journal
+                .entries()?
+                .into_iter()
+                .filter(|entry| match entry {
+                    Entry::Auction(a) => {
+                        matches_filter(&a.controller, &controller)
+                            && matches_filter(&a.vault, &vault)
+                    }
+                    // purchases are not of a vault
+                    Entry::Purchase(p) => {
+                        matches_filter(&p.controller, &controller) && vault.is_none()
+                    }
+                })
+                .collect();
+            let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+            for entry in entries.iter().skip(skip) {
+                match entry {
+                    Entry::Auction(attempt) => println!(
+                        "{} {:?} controller {} vault {} collateral {} id {} price {} block {} tx {} gas {} auction {} {}",
+                        attempt.timestamp,
+                        attempt.outcome,
+                        attempt.controller,
+                        attempt.vault,
+                        attempt.collateral,
+                        attempt.token_id,
+                        attempt.oracle_price,
+                        attempt
+                            .snapshot_block
+                            .map_or("-".to_string(), |b| b.to_string()),
+                        attempt
+                            .tx_hash
+                            .map_or("-".to_string(), |h| format!("{:?}", h)),
+                        attempt.gas_used.map_or("-".to_string(), |g| g.to_string()),
+                        attempt.auction_id.map_or("-".to_string(), |a| a.to_string()),
+                        attempt.error.as_deref().unwrap_or("")
+                    ),
+                    Entry::Purchase(decision) => println!(
+                        "{} {:?} controller {} auction {} collection {} id {} block {} price {} cost {} proceeds {} gas {} {}",
+                        decision.timestamp,
+                        decision.decision,
+                        decision.controller,
+                        decision.auction_id,
+                        decision.collection,
+                        decision.token_id,
+                        decision.snapshot_block,
+                        decision.price.map_or("-".to_string(), |p| p.to_string()),
+                        decision.cost.map_or("-".to_string(), |c| c.to_string()),
+                        decision.proceeds.map_or("-".to_string(), |p| p.to_string()),
+                        decision.gas_cost.map_or("-".to_string(), |g| g.to_string()),
+                        decision.error.as_deref().unwrap_or("")
+                    ),
+                }
+            }
+        }
+        Command::Price {
+            collection,
+            currency,
+            kind,
+            twap_seconds,
+        } => {
+            let oracle_response = reservoir
+                .max_collection_bid(&collection, kind, &currency, twap_seconds)
+                .await?;
+            println!("{}", oracle_response.price);
+        }
+        Command::BidsDepth {
+            collection,
+            percent,
+        } => {
+            let count = collection_bids_gt_percent_of_top_bid(&collection, percent).await?;
+            println!("{} bids >= {}% of top bid", count, percent * 100.0);
+        }
     }
 
     Ok(())
 }
 
+/// Runs one liquidation pass, or one every `interval` until shutdown.
+async fn liquidate(
+    reservoir: &ReservoirClient,
+    vaults: &impl VaultSource,
+    config: &Config,
+    journal: &Journal,
+    interval: Option<Duration>,
+    dry_run: bool,
+) {
+    match interval {
+        Some(interval) => {
+            let oracle = PrefetchedOracle::new(reservoir);
+            run_until_shutdown(interval, || async {
+                start_liquidations_for_whitelisted_controllers(
+                    &oracle,
+                    vaults,
+                    PaprController::new,
+                    config,
+                    journal,
+                    dry_run,
+                )
+                .await?;
+                // wake up for the vaults becoming liquidatable before the next pass
+                prefetch_upcoming(&oracle, vaults, config, interval.as_secs()).await
+            })
+            .await
+        }
+        None => {
+            if let Err(err) = start_liquidations_for_whitelisted_controllers(
+                reservoir,
+                vaults,
+                PaprController::new,
+                config,
+                journal,
+                dry_run,
+            )
+            .await
+            {
+                println!("{}", err);
+            }
+        }
+    }
+}
+
+fn print_watchlist(watchlist: &[WatchlistEntry]) {
+    for entry in watchlist {
+        println!(
+            "{} controller {} vault {:?} collateral {:?} debt {}",
+            entry.liquidates_at, entry.controller, entry.account, entry.collateral, entry.debt
+        );
+    }
+}
+
+fn matches_filter(value: &str, filter: &Option<String>) -> bool {
+    match filter {
+        Some(f) => value.eq_ignore_ascii_case(f),
+        None => true,
+    }
+}
+
 async fn collection_bids_gt_percent_of_top_bid(
     collection: &str,
     percent: f64,
@@ -31,7 +300,7 @@ async fn collection_bids_gt_percent_of_top_bid(
         .max_collection_bid(
             collection,
             PriceKind::Twap,
-            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+            USDC,
             None, // 604800 = 7 days
         )
         .await?;
@@ -43,13 +312,12 @@ async fn collection_bids_gt_percent_of_top_bid(
 
 async fn collection_bids_gte(collection: &str, price: f64) -> Result<usize, eyre::Error> {
     let count = ReservoirClient::default()
-        .bids(&collection, Some(1000))
+        .bids(collection, Some(1000))
         .await?
         .orders
         .into_iter()
         .filter(|o| o.price.amount.usd >= price)
-        .collect::<Vec<reservoir::orders::Order>>()
-        .len();
+        .count();
 
-    return Ok(count);
+    Ok(count)
 }
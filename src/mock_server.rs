@@ -0,0 +1,139 @@
+//! Local HTTP stand-ins for Reservoir, the subgraph and the RPC node, serving
+//! recorded fixtures so clients can be tested without the network.
+use reqwest::Url;
+use serde_json::Value;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// A request the server received.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    /// lowercase names
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).expect("request body is JSON")
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Option<Value> + Send + Sync;
+
+/// Answers each request with the JSON `handler` returns, or a 404 for `None`,
+/// on a background thread that lives as long as the test binary.
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&Request) -> Option<Value> + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        let handler: Box<Handler> = Box::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(err) = serve(stream, &handler, &received) {
+                    println!("mock server error: {}", err);
+                }
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Answers one request, closing the connection so clients do not reuse it.
+fn serve(
+    mut stream: TcpStream,
+    handler: &Handler,
+    received: &Mutex<Vec<Request>>,
+) -> Result<(), eyre::Error> {
+    let request = read_request(&mut stream)?;
+    let (status, body) = match handler(&request) {
+        Some(body) => ("200 OK", body.to_string()),
+        None => ("404 Not Found", "{}".to_string()),
+    };
+    received.lock().unwrap().push(request);
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request, eyre::Error> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = Url::parse(&format!("http://mock{}", parts.next().unwrap_or("/")))?;
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map_or(Ok(0), |(_, value)| value.parse::<usize>())?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path: target.path().to_string(),
+        query: target.query_pairs().into_owned().collect(),
+        headers,
+        body: String::from_utf8(body)?,
+    })
+}
+
+/// A recorded response body from `src/fixtures`.
+pub fn fixture(json: &str) -> Value {
+    serde_json::from_str(json).expect("fixture is JSON")
+}
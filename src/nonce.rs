@@ -0,0 +1,133 @@
+use ethers::types::U256;
+use std::future::Future;
+use tokio::sync::Mutex;
+
+/// Hands out a signer's nonces locally so a transaction can be sent before the
+/// previous one is mined. The node is only asked for the account's pending
+/// transaction count at first, again after a send fails and once a nonce is
+/// given up on.
+#[derive(Default)]
+pub struct NonceManager {
+    next: Mutex<Option<U256>>,
+}
+
+impl NonceManager {
+    /// Sends with the next nonce, holding it until `send` returns so transactions
+    /// reach the node in nonce order. A failed send leaves its nonce unused, so
+    /// the local count is forgotten. A nonce the node says is used, e.g. by a
+    /// transaction sent from the same key without the manager, is counted
+    /// again and the send retried once.
+    pub async fn send<F, C, S, Fut, T>(&self, pending_count: F, send: S) -> Result<T, eyre::Error>
+    where
+        F: Fn() -> C,
+        C: Future<Output = Result<U256, eyre::Error>>,
+        S: Fn(U256) -> Fut,
+        Fut: Future<Output = Result<T, eyre::Error>>,
+    {
+        let mut next = self.next.lock().await;
+        let mut nonce = match *next {
+            Some(nonce) => nonce,
+            None => pending_count().await?,
+        };
+        *next = None;
+        let mut sent = send(nonce).await;
+        if matches!(&sent, Err(err) if nonce_too_low(err)) {
+            nonce = pending_count().await?;
+            sent = send(nonce).await;
+        }
+        *next = sent.as_ref().ok().map(|_| nonce + 1);
+        sent
+    }
+
+    /// Forgets the local count so the next send asks the node, e.g. once a
+    /// transaction is dropped and its nonce would be left a gap.
+    pub async fn reset(&self) {
+        *self.next.lock().await = None;
+    }
+}
+
+fn nonce_too_low(err: &eyre::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.to_string().to_lowercase().contains("nonce too low"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nonce::NonceManager;
+    use ethers::types::U256;
+
+    #[tokio::test]
+    async fn counts_up_from_pending_count_until_a_send_fails() {
+        let nonces = NonceManager::default();
+        let pending = |count: u64| move || async move { Ok(U256::from(count)) };
+        let sent = |nonce| async move { Ok(nonce) };
+        assert_eq!(nonces.send(pending(5), sent).await.unwrap(), U256::from(5));
+        // the node is not asked again while counting locally
+        assert_eq!(nonces.send(pending(0), sent).await.unwrap(), U256::from(6));
+
+        let failed = nonces
+            .send(pending(0), |_| async {
+                Err::<U256, _>(eyre::eyre!("rejected"))
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(nonces.send(pending(7), sent).await.unwrap(), U256::from(7));
+    }
+
+    #[tokio::test]
+    async fn failed_count_sends_nothing() {
+        let nonces = NonceManager::default();
+        let result: Result<U256, _> = nonces
+            .send(
+                || async { Err(eyre::eyre!("rpc down")) },
+                |_| async { panic!("sent without a nonce") },
+            )
+            .await;
+        assert!(result.is_err());
+        let sent = nonces.send(
+            || async { Ok(U256::from(3)) },
+            |nonce| async move { Ok(nonce) },
+        );
+        assert_eq!(sent.await.unwrap(), U256::from(3));
+    }
+
+    #[tokio::test]
+    async fn recounts_once_when_nonce_is_too_low() {
+        let nonces = NonceManager::default();
+        let pending = || async { Ok(U256::from(5)) };
+        let sent = |nonce| async move { Ok(nonce) };
+        nonces.send(pending, sent).await.unwrap();
+        // a purchase sent from the same key took nonce 6
+        let pending = || async { Ok(U256::from(7)) };
+        let used_below_7 = |nonce: U256| async move {
+            if nonce < U256::from(7) {
+                return Err(eyre::eyre!(
+                    "(code: -32000, message: nonce too low, data: None)"
+                ));
+            }
+            Ok(nonce)
+        };
+        assert_eq!(
+            nonces.send(pending, used_below_7).await.unwrap(),
+            U256::from(7)
+        );
+        assert_eq!(nonces.send(pending, sent).await.unwrap(), U256::from(8));
+
+        let always_too_low = |_| async { Err::<U256, _>(eyre::eyre!("nonce too low")) };
+        assert!(nonces.send(pending, always_too_low).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reset_asks_the_node_again() {
+        let nonces = NonceManager::default();
+        let sent = |nonce| async move { Ok(nonce) };
+        nonces
+            .send(|| async { Ok(U256::from(5)) }, sent)
+            .await
+            .unwrap();
+        // nonce 5 was dropped, the next send fills the gap
+        nonces.reset().await;
+        let sent = nonces.send(|| async { Ok(U256::from(5)) }, sent).await;
+        assert_eq!(sent.unwrap(), U256::from(5));
+    }
+}
@@ -1,46 +1,766 @@
-use crate::provider::PROVIDER;
+use crate::{
+    erc20,
+    nonce::NonceManager,
+    projection::Funding,
+    provider::{PROVIDER, PROVIDER_NONCES},
+    uniswap,
+};
 use ethers::{
+    abi::{AbiDecode, RawLog},
+    contract::EthLogDecode,
     core::k256::ecdsa::SigningKey,
-    middleware::SignerMiddleware,
-    prelude::{abigen, TransactionReceipt},
-    providers::{Http, Provider},
-    signers::Wallet,
-    types::{Address, U256},
+    middleware::{signer::SignerMiddlewareError, SignerMiddleware},
+    prelude::{abigen, ContractError, TransactionReceipt},
+    providers::{Http, HttpClientError, Middleware, Provider, ProviderError},
+    signers::{Signer, Wallet},
+    types::{transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes, H256, U256},
+    utils::rlp::Rlp,
 };
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 abigen!(PaprControllerABI, "src/abis/PaprController.json");
+
+/// How much more a replacement pays per gas, nodes require at least 10%
+const FEE_BUMP_PERCENT: u64 = 20;
+
+pub type Client = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
+
 pub struct PaprController {
-    controller: PaprControllerABI<SignerMiddleware<Provider<Http>, Wallet<SigningKey>>>,
+    controller: PaprControllerABI<Client>,
+    /// shared by every controller sending from the same signer
+    nonces: Arc<NonceManager>,
+}
+
+/// A liquidation auction the bot started, decoded from the `StartAuction` event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartedAuction {
+    pub auction_id: U256,
+    pub nft: Collateral,
+    pub nft_owner: Address,
+    pub start_price: U256,
+    pub per_period_decay_percent_wad: U256,
+    pub seconds_in_period: U256,
+    pub payment_asset: Address,
+}
+
+impl StartedAuction {
+    /// Finds the auction `controller` started in the transaction, if any.
+    pub fn from_receipt(receipt: &TransactionReceipt, controller: Address) -> Option<Self> {
+        receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == controller)
+            .find_map(|log| {
+                StartAuctionFilter::decode_log(&RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.to_vec(),
+                })
+                .ok()
+            })
+            .map(|event| Self {
+                auction_id: event.auction_id,
+                nft: Collateral {
+                    addr: event.auction_asset_contract,
+                    id: event.auction_asset_id,
+                },
+                nft_owner: event.nft_owner,
+                start_price: event.start_price,
+                per_period_decay_percent_wad: event.per_period_decay_percent_wad,
+                seconds_in_period: event.seconds_in_period,
+                payment_asset: event.payment_asset,
+            })
+    }
+}
+
+/// The controller reads and transactions liquidation needs, so it can run
+/// against a fake in tests.
+pub trait ControllerExecutor {
+    async fn latest_block_timestamp(&self) -> Result<u64, eyre::Error>;
+
+    /// Address oracle messages must be signed by
+    async fn oracle_signer(&self) -> Result<Address, eyre::Error>;
+
+    async fn new_target(&self) -> Result<U256, eyre::Error>;
+
+    async fn funding(&self) -> Result<Funding, eyre::Error>;
+
+    async fn liquidation_auction_min_spacing(&self) -> Result<U256, eyre::Error>;
+
+    async fn vault_info(&self, account: Address, asset: Address) -> Result<VaultInfo, eyre::Error>;
+
+    /// Runs `startLiquidationAuction` as an `eth_call` so a revert costs no gas.
+    async fn simulate_start_liquidation_auction(
+        &self,
+        account: Address,
+        collateral: Collateral,
+        oracle_info: OracleInfo,
+    ) -> Result<(), SimulationError>;
+
+    /// Signs and broadcasts `startLiquidationAuction` with the signer's next
+    /// nonce, returning without waiting for it to be mined.
+    async fn send_start_liquidation_auction(
+        &self,
+        account: Address,
+        collateral: Collateral,
+        oracle_info: OracleInfo,
+    ) -> Result<SentTransaction, eyre::Error>;
+
+    /// `None` until the transaction is mined
+    async fn liquidation_receipt(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Option<LiquidationReceipt>, eyre::Error>;
+
+    /// Transactions of the signer mined so far, the nonce its next mined
+    /// transaction will have.
+    async fn confirmed_nonce(&self) -> Result<U256, eyre::Error>;
+
+    /// Whether the node still knows the transaction, mined or in its mempool
+    async fn transaction_known(&self, tx_hash: H256) -> Result<bool, eyre::Error>;
+
+    /// Broadcasts a signed transaction again, e.g. after the mempool dropped it
+    async fn rebroadcast(&self, raw: Bytes) -> Result<(), eyre::Error>;
+
+    /// Signs the transaction `raw` again at its nonce with higher fees and
+    /// broadcasts it, to replace one stuck in the mempool.
+    async fn replace(&self, raw: Bytes) -> Result<SentTransaction, eyre::Error>;
+
+    /// Forgets the signer's local nonce count, once a nonce is given up on.
+    async fn reset_nonces(&self);
+}
+
+/// The chain reads and transactions purchasing auctions needs, so it can run
+/// against a fake in tests.
+pub trait PurchaseExecutor {
+    /// Account that pays for purchases and receives the NFTs
+    fn bot(&self) -> Address;
+
+    /// Number and timestamp of the latest block, for reads that must agree with
+    /// each other on the block
+    async fn latest_block(&self) -> Result<(u64, u64), eyre::Error>;
+
+    async fn gas_price(&self) -> Result<U256, eyre::Error>;
+
+    async fn papr(&self) -> Result<Address, eyre::Error>;
+
+    async fn underlying(&self) -> Result<Address, eyre::Error>;
+
+    /// The Uniswap v3 pool papr trades against underlying in
+    async fn pool(&self) -> Result<Address, eyre::Error>;
+
+    async fn auction_creator_discount_percent_wad(&self) -> Result<U256, eyre::Error>;
+
+    /// Address oracle messages must be signed by
+    async fn oracle_signer(&self) -> Result<Address, eyre::Error>;
+
+    /// Price as of `block`, discounted if the bot started the auction
+    async fn auction_current_price(
+        &self,
+        auction: Auction,
+        block: u64,
+    ) -> Result<U256, eyre::Error>;
+
+    /// The bot's balance of `token`
+    async fn balance_of(&self, token: Address) -> Result<U256, eyre::Error>;
+
+    /// Amount of `token_in` it costs to buy `amount_out` of `token_out` from `pool`
+    async fn quote_exact_output(
+        &self,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, eyre::Error>;
+
+    /// Buys exactly `amount_out` of `token_out` from `pool`, paying at most
+    /// `max_amount_in` of `token_in`. Returns the amount paid.
+    async fn swap_exact_output(
+        &self,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+        max_amount_in: U256,
+    ) -> Result<U256, eyre::Error>;
+
+    /// Approves `spender` for `amount` of the bot's `token` unless it already is
+    async fn ensure_allowance(
+        &self,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), eyre::Error>;
+
+    /// Buys the auctioned NFT for at most `max_price` papr, pulled from the bot's
+    /// balance, sending it to `send_to`.
+    async fn purchase_liquidation_auction_nft(
+        &self,
+        auction: Auction,
+        max_price: U256,
+        send_to: Address,
+        oracle_info: OracleInfo,
+    ) -> Result<TransactionReceipt, eyre::Error>;
+
+    /// Sends a transaction built elsewhere, e.g. a step of selling the NFT, and
+    /// waits for it to be mined
+    async fn send_transaction(
+        &self,
+        tx: TypedTransaction,
+    ) -> Result<TransactionReceipt, eyre::Error>;
+}
+
+/// A signed transaction that was broadcast but may not be mined yet
+#[derive(Clone, Debug)]
+pub struct SentTransaction {
+    pub hash: H256,
+    pub nonce: U256,
+    /// signed RLP, kept to rebroadcast it
+    pub raw: Bytes,
+}
+
+pub struct LiquidationReceipt {
+    pub receipt: TransactionReceipt,
+    /// `None` if the transaction reverted
+    pub auction: Option<StartedAuction>,
+}
+
+/// Why a simulated controller call failed.
+#[derive(Debug)]
+pub enum SimulationError {
+    /// the call reverted with one of the controller's custom errors
+    Reverted(PaprControllerABIErrors),
+    /// the call failed without a revert reason we could decode, e.g. an RPC error
+    Failed(eyre::Error),
+}
+
+impl SimulationError {
+    /// The revert may go away on its own, e.g. the oracle message is ahead of the
+    /// latest block, so the call is worth trying again shortly.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            SimulationError::Reverted(PaprControllerABIErrors::OracleMessageTimestampInvalid(_))
+        )
+    }
+
+    fn from_contract_error(err: ContractError<Client>) -> Self {
+        match revert_data(&err).and_then(|data| PaprControllerABIErrors::decode(data).ok()) {
+            Some(decoded) => SimulationError::Reverted(decoded),
+            None => SimulationError::Failed(err.into()),
+        }
+    }
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::Reverted(err) => write!(f, "reverted with {:?}", err),
+            SimulationError::Failed(err) => write!(f, "call failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Digs the revert data out of a failed `eth_call`. Nodes return it as the
+/// `data` of the JSON-RPC error, either directly or nested under another `data`.
+fn revert_data(err: &ContractError<Client>) -> Option<Bytes> {
+    let ContractError::MiddlewareError(SignerMiddlewareError::MiddlewareError(
+        ProviderError::JsonRpcClientError(err),
+    )) = err
+    else {
+        return None;
+    };
+    let Some(HttpClientError::JsonRpcError(rpc_error)) = err.downcast_ref::<HttpClientError>()
+    else {
+        return None;
+    };
+    let data = rpc_error.data.as_ref()?;
+    data.as_str()
+        .or_else(|| data.get("data").and_then(|d| d.as_str()))
+        .and_then(|hex| hex.parse::<Bytes>().ok())
 }
 
 impl PaprController {
     pub fn new(controller_addr_str: &str) -> Result<Self, eyre::Error> {
+        Self::with_client(
+            controller_addr_str,
+            Arc::clone(&PROVIDER),
+            Arc::clone(&PROVIDER_NONCES),
+        )
+    }
+
+    /// Sends through `client` instead of the global provider, e.g. a local node
+    pub fn with_client(
+        controller_addr_str: &str,
+        client: Arc<Client>,
+        nonces: Arc<NonceManager>,
+    ) -> Result<Self, eyre::Error> {
         let controller_addr = controller_addr_str.parse::<Address>()?;
 
         Ok(Self {
-            controller: PaprControllerABI::new(controller_addr, Arc::clone(&PROVIDER)),
+            controller: PaprControllerABI::new(controller_addr, client),
+            nonces,
         })
     }
+}
+
+impl ControllerExecutor for PaprController {
+    async fn latest_block_timestamp(&self) -> Result<u64, eyre::Error> {
+        let block = self
+            .controller
+            .client()
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(eyre::eyre!("latest block not found"))?;
+        Ok(block.timestamp.as_u64())
+    }
+
+    async fn oracle_signer(&self) -> Result<Address, eyre::Error> {
+        Ok(self.controller.oracle_signer().call().await?)
+    }
 
-    pub async fn new_target(&self) -> Result<U256, eyre::Error> {
+    async fn new_target(&self) -> Result<U256, eyre::Error> {
         Ok(self.controller.new_target().call().await?)
     }
 
-    pub async fn start_liquidation_auction(
+    async fn funding(&self) -> Result<Funding, eyre::Error> {
+        Ok(Funding {
+            target: self.controller.target().call().await?,
+            mark: self.controller.mark().call().await?,
+            funding_period: self.controller.funding_period().call().await?,
+            last_updated: self.controller.last_updated().call().await?.as_u64(),
+        })
+    }
+
+    async fn liquidation_auction_min_spacing(&self) -> Result<U256, eyre::Error> {
+        Ok(self
+            .controller
+            .liquidation_auction_min_spacing()
+            .call()
+            .await?)
+    }
+
+    async fn vault_info(&self, account: Address, asset: Address) -> Result<VaultInfo, eyre::Error> {
+        Ok(self.controller.vault_info(account, asset).call().await?)
+    }
+
+    async fn simulate_start_liquidation_auction(
         &self,
         account: Address,
         collateral: Collateral,
         oracle_info: OracleInfo,
-    ) -> Result<TransactionReceipt, eyre::Error> {
+    ) -> Result<(), SimulationError> {
         self.controller
             .start_liquidation_auction(account, collateral, oracle_info)
+            .call()
+            .await
+            .map_err(SimulationError::from_contract_error)?;
+        Ok(())
+    }
+
+    async fn send_start_liquidation_auction(
+        &self,
+        account: Address,
+        collateral: Collateral,
+        oracle_info: OracleInfo,
+    ) -> Result<SentTransaction, eyre::Error> {
+        let client = self.controller.client();
+        let mut tx = self
+            .controller
+            .start_liquidation_auction(account, collateral, oracle_info)
+            .tx;
+        // estimates gas, the nonce it fills in is replaced with ours
+        client.fill_transaction(&mut tx, None).await?;
+        let (client, tx) = (&client, &tx);
+        self.nonces
+            .send(
+                || async {
+                    Ok(client
+                        .get_transaction_count(client.address(), Some(BlockNumber::Pending.into()))
+                        .await?)
+                },
+                |nonce| async move {
+                    let mut tx = tx.clone();
+                    tx.set_nonce(nonce);
+                    sign_and_send(client, tx).await
+                },
+            )
+            .await
+    }
+
+    async fn liquidation_receipt(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Option<LiquidationReceipt>, eyre::Error> {
+        let Some(receipt) = self
+            .controller
+            .client()
+            .get_transaction_receipt(tx_hash)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let auction = StartedAuction::from_receipt(&receipt, self.controller.address());
+        Ok(Some(LiquidationReceipt { receipt, auction }))
+    }
+
+    async fn confirmed_nonce(&self) -> Result<U256, eyre::Error> {
+        let client = self.controller.client();
+        Ok(client
+            .get_transaction_count(client.address(), Some(BlockNumber::Latest.into()))
+            .await?)
+    }
+
+    async fn transaction_known(&self, tx_hash: H256) -> Result<bool, eyre::Error> {
+        Ok(self
+            .controller
+            .client()
+            .get_transaction(tx_hash)
+            .await?
+            .is_some())
+    }
+
+    async fn rebroadcast(&self, raw: Bytes) -> Result<(), eyre::Error> {
+        self.controller.client().send_raw_transaction(raw).await?;
+        Ok(())
+    }
+
+    async fn replace(&self, raw: Bytes) -> Result<SentTransaction, eyre::Error> {
+        let (mut tx, _) = TypedTransaction::decode_signed(&Rlp::new(&raw))?;
+        bump_fees(&mut tx);
+        sign_and_send(&self.controller.client(), tx).await
+    }
+
+    async fn reset_nonces(&self) {
+        self.nonces.reset().await;
+    }
+}
+
+/// Signs `tx`, nonce and fees filled in, and broadcasts it.
+async fn sign_and_send(
+    client: &Client,
+    tx: TypedTransaction,
+) -> Result<SentTransaction, eyre::Error> {
+    let nonce = *tx
+        .nonce()
+        .ok_or(eyre::eyre!("transaction without a nonce"))?;
+    let signature = client.signer().sign_transaction(&tx).await?;
+    let raw = tx.rlp_signed(&signature);
+    client.send_raw_transaction(raw.clone()).await?;
+    Ok(SentTransaction {
+        hash: tx.hash(&signature),
+        nonce,
+        raw,
+    })
+}
+
+/// Raises every fee `tx` pays by `FEE_BUMP_PERCENT`, at least by 1 wei.
+fn bump_fees(tx: &mut TypedTransaction) {
+    let bump = |fee: U256| (fee * (100 + FEE_BUMP_PERCENT) / 100).max(fee + 1);
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            inner.max_fee_per_gas = inner.max_fee_per_gas.map(bump);
+            inner.max_priority_fee_per_gas = inner.max_priority_fee_per_gas.map(bump);
+        }
+        _ => {
+            if let Some(gas_price) = tx.gas_price() {
+                tx.set_gas_price(bump(gas_price));
+            }
+        }
+    }
+}
+
+impl PurchaseExecutor for PaprController {
+    fn bot(&self) -> Address {
+        self.controller.client().address()
+    }
+
+    async fn latest_block(&self) -> Result<(u64, u64), eyre::Error> {
+        let block = self
+            .controller
+            .client()
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(eyre::eyre!("latest block not found"))?;
+        let number = block
+            .number
+            .ok_or(eyre::eyre!("latest block has no number"))?;
+        Ok((number.as_u64(), block.timestamp.as_u64()))
+    }
+
+    async fn gas_price(&self) -> Result<U256, eyre::Error> {
+        Ok(self.controller.client().get_gas_price().await?)
+    }
+
+    async fn papr(&self) -> Result<Address, eyre::Error> {
+        Ok(self.controller.papr().call().await?)
+    }
+
+    async fn underlying(&self) -> Result<Address, eyre::Error> {
+        Ok(self.controller.underlying().call().await?)
+    }
+
+    async fn pool(&self) -> Result<Address, eyre::Error> {
+        Ok(self.controller.pool().call().await?)
+    }
+
+    async fn auction_creator_discount_percent_wad(&self) -> Result<U256, eyre::Error> {
+        Ok(self
+            .controller
+            .auction_creator_discount_percent_wad()
+            .call()
+            .await?)
+    }
+
+    async fn oracle_signer(&self) -> Result<Address, eyre::Error> {
+        ControllerExecutor::oracle_signer(self).await
+    }
+
+    async fn auction_current_price(
+        &self,
+        auction: Auction,
+        block: u64,
+    ) -> Result<U256, eyre::Error> {
+        Ok(self
+            .controller
+            .auction_current_price(auction)
+            .block(block)
+            .call()
+            .await?)
+    }
+
+    async fn balance_of(&self, token: Address) -> Result<U256, eyre::Error> {
+        erc20::balance_of(&self.controller.client(), token, self.bot()).await
+    }
+
+    async fn quote_exact_output(
+        &self,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, eyre::Error> {
+        uniswap::quote_exact_output(
+            &self.controller.client(),
+            pool,
+            token_in,
+            token_out,
+            amount_out,
+        )
+        .await
+    }
+
+    async fn swap_exact_output(
+        &self,
+        pool: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+        max_amount_in: U256,
+    ) -> Result<U256, eyre::Error> {
+        uniswap::swap_exact_output(
+            &self.controller.client(),
+            pool,
+            token_in,
+            token_out,
+            amount_out,
+            max_amount_in,
+        )
+        .await
+    }
+
+    async fn ensure_allowance(
+        &self,
+        token: Address,
+        spender: Address,
+        amount: U256,
+    ) -> Result<(), eyre::Error> {
+        erc20::ensure_allowance(&self.controller.client(), token, spender, amount).await
+    }
+
+    async fn purchase_liquidation_auction_nft(
+        &self,
+        auction: Auction,
+        max_price: U256,
+        send_to: Address,
+        oracle_info: OracleInfo,
+    ) -> Result<TransactionReceipt, eyre::Error> {
+        let receipt = self
+            .controller
+            .purchase_liquidation_auction_nft(auction, max_price, send_to, oracle_info)
             .send()
             .await?
             .await?
             .ok_or(eyre::eyre!(
-                "start_liquidation_auction no transaction receipt"
-            ))
-        // TODO could dig in the logs here to return the auction object
+                "purchase_liquidation_auction_nft no transaction receipt"
+            ))?;
+        Ok(receipt)
+    }
+
+    async fn send_transaction(
+        &self,
+        tx: TypedTransaction,
+    ) -> Result<TransactionReceipt, eyre::Error> {
+        self.controller
+            .client()
+            .send_transaction(tx, None)
+            .await?
+            .await?
+            .ok_or(eyre::eyre!("no transaction receipt"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::papr_controller::{
+        bump_fees, revert_data, Client, Collateral, NotLiquidatable, PaprControllerABIErrors,
+        SimulationError, StartedAuction,
+    };
+    use ethers::{
+        abi::{AbiEncode, Token},
+        contract::EthEvent,
+        middleware::signer::SignerMiddlewareError,
+        prelude::ContractError,
+        prelude::TransactionReceipt,
+        providers::{HttpClientError, ProviderError},
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, Address, Eip1559TransactionRequest, Log,
+            TransactionRequest, H256, U256,
+        },
+        utils::rlp::Rlp,
+    };
+
+    #[test]
+    fn from_contract_error_decodes_custom_error() {
+        let data = format!("0x{}", ethers::utils::hex::encode(NotLiquidatable.encode()));
+        let err = SimulationError::from_contract_error(rpc_error(data.into()));
+        assert!(matches!(
+            err,
+            SimulationError::Reverted(PaprControllerABIErrors::NotLiquidatable(_))
+        ));
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn from_contract_error_reads_nested_revert_data() {
+        let data = format!("0x{}", ethers::utils::hex::encode(NotLiquidatable.encode()));
+        let err = rpc_error(serde_json::json!({ "data": data }));
+        assert_eq!(
+            revert_data(&err).unwrap().to_vec(),
+            NotLiquidatable.encode()
+        );
+    }
+
+    #[test]
+    fn from_contract_error_keeps_unknown_reverts() {
+        let err = SimulationError::from_contract_error(rpc_error("0xdeadbeef".into()));
+        assert!(matches!(err, SimulationError::Failed(_)));
+    }
+
+    #[test]
+    fn from_receipt_decodes_start_auction_event() {
+        let controller: Address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            .parse()
+            .unwrap();
+        let nft: Address = "0x79ab709dadc05cd2c0f7322bc7e3d70d2550942c"
+            .parse()
+            .unwrap();
+        let owner: Address = "0xbc3ed6b537f2980e66f396fe14210a56ba3f72c4"
+            .parse()
+            .unwrap();
+        let papr: Address = "0x047067ad8b5bf37bb93bb61af73f73fd9f8ca5af"
+            .parse()
+            .unwrap();
+        let log = Log {
+            address: controller,
+            topics: vec![
+                super::StartAuctionFilter::signature(),
+                H256::from_low_u64_be(42),
+                H256::from_low_u64_be(10),
+                H256::from(nft),
+            ],
+            data: ethers::abi::encode(&[
+                Token::Address(owner),
+                Token::Uint(U256::from_dec_str("700000000000000000").unwrap()),
+                Token::Uint(U256::from(86400)),
+                Token::Uint(U256::from_dec_str("286202279878974014").unwrap()),
+                Token::Address(papr),
+            ])
+            .into(),
+            ..Default::default()
+        };
+        // a log from another contract with the same signature is ignored
+        let other = Log {
+            address: Address::zero(),
+            ..log.clone()
+        };
+        let receipt = TransactionReceipt {
+            logs: vec![other, log],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            StartedAuction::from_receipt(&receipt, controller).unwrap(),
+            StartedAuction {
+                auction_id: U256::from(42),
+                nft: Collateral {
+                    addr: nft,
+                    id: U256::from(10),
+                },
+                nft_owner: owner,
+                start_price: U256::from_dec_str("286202279878974014").unwrap(),
+                per_period_decay_percent_wad: U256::from_dec_str("700000000000000000").unwrap(),
+                seconds_in_period: U256::from(86400),
+                payment_asset: papr,
+            }
+        );
+        assert!(StartedAuction::from_receipt(&receipt, papr).is_none());
+    }
+
+    #[tokio::test]
+    async fn bumped_transaction_keeps_its_nonce_and_pays_more() {
+        let wallet = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(1u64);
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::from_low_u64_be(1))
+            .nonce(7)
+            .gas(100_000)
+            .max_fee_per_gas(50)
+            .max_priority_fee_per_gas(2)
+            .chain_id(1)
+            .into();
+        let raw = tx.rlp_signed(&wallet.sign_transaction(&tx).await.unwrap());
+
+        // decoded as `replace` does
+        let (mut replacement, _) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+        bump_fees(&mut replacement);
+        assert_eq!(replacement.nonce(), Some(&U256::from(7)));
+        let TypedTransaction::Eip1559(replacement) = replacement else {
+            panic!("not an EIP-1559 transaction");
+        };
+        assert_eq!(replacement.max_fee_per_gas, Some(U256::from(60)));
+        assert_eq!(replacement.max_priority_fee_per_gas, Some(U256::from(3)));
+
+        let mut legacy: TypedTransaction = TransactionRequest::new().gas_price(1).into();
+        bump_fees(&mut legacy);
+        assert_eq!(legacy.gas_price(), Some(U256::from(2)));
+    }
+
+    fn rpc_error(data: serde_json::Value) -> ContractError<Client> {
+        let json_rpc_error = serde_json::from_value(serde_json::json!({
+            "code": 3,
+            "message": "execution reverted",
+            "data": data,
+        }))
+        .unwrap();
+        ContractError::MiddlewareError(SignerMiddlewareError::MiddlewareError(
+            ProviderError::JsonRpcClientError(Box::new(HttpClientError::JsonRpcError(
+                json_rpc_error,
+            ))),
+        ))
     }
 }
@@ -1,53 +1,163 @@
-use ethers::types::U256;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::U256,
+};
 use graphql_client::{GraphQLQuery, QueryBody, Response};
 use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::env;
+use std::{env, fmt};
 
 use crate::papr_subgraph::queries::{
-    all_controllers, collateral_by_controller, ongoing_auctions_by_controller,
-    ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as Auctions,
-    vaults_exceeding_debt_per_collateral, AllControllers, CollateralByController,
-    OngoingAuctionsByController, VaultsExceedingDebtPerCollateral,
+    collateral_by_controller, controller_by_id, ongoing_auctions_by_controller,
+    ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as Auctions, subgraph_meta,
+    vaults_exceeding_debt_per_collateral, CollateralByController, ControllerByID,
+    OngoingAuctionsByController, SubgraphMeta, VaultsExceedingDebtPerCollateral,
 };
+use crate::provider::latest_block_number;
+
+/// The most entities The Graph returns for one query
+const PAGE_SIZE: i64 = 1000;
 
 static SUBGRAPH_URL: Lazy<String> =
     Lazy::new(|| env::var("PAPR_SUBGRAPH_URL").expect("PAPR_SUBGRAPH_URL not set"));
 
+/// Why a subgraph query returned no usable data.
+#[derive(Debug)]
+pub enum SubgraphError {
+    /// the subgraph answered with errors, e.g. an invalid query or failed indexing
+    GraphQL(Vec<graphql_client::Error>),
+    /// the response had neither data nor errors
+    MissingData,
+}
+
+impl fmt::Display for SubgraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubgraphError::GraphQL(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "subgraph errors: {}", messages.join("; "))
+            }
+            SubgraphError::MissingData => write!(f, "missing response data for query"),
+        }
+    }
+}
+
+impl std::error::Error for SubgraphError {}
+
+/// The block the subgraph has indexed up to.
+#[derive(Debug, PartialEq)]
+pub struct IndexingStatus {
+    pub block_number: u64,
+    pub has_indexing_errors: bool,
+}
+
+impl IndexingStatus {
+    /// Errors unless the subgraph indexed cleanly to within `max_lag_blocks` of `chain_head`.
+    pub fn ensure_healthy(&self, chain_head: u64, max_lag_blocks: u64) -> Result<(), eyre::Error> {
+        if self.has_indexing_errors {
+            return Err(eyre::eyre!(
+                "subgraph has indexing errors at block {}",
+                self.block_number
+            ));
+        }
+        let lag = chain_head.saturating_sub(self.block_number);
+        if lag > max_lag_blocks {
+            return Err(eyre::eyre!(
+                "subgraph is {} blocks behind the chain, at most {} allowed",
+                lag,
+                max_lag_blocks
+            ));
+        }
+        Ok(())
+    }
+}
+
 pub struct GraphQLClient {
     client: reqwest::Client,
+    /// `PAPR_SUBGRAPH_URL` if not given
+    url: Option<String>,
+    /// node the subgraph's lag is measured against, `PROVIDER` if not given
+    node: Option<Provider<Http>>,
 }
 
 impl Default for GraphQLClient {
     fn default() -> Self {
         Self {
             client: reqwest::Client::new(),
+            url: None,
+            node: None,
         }
     }
 }
 
+/// List queries take an optional `block` to read the subgraph as of that block,
+/// so one pass over controllers, vaults and auctions sees a consistent snapshot.
 impl GraphQLClient {
-    /// TODO screen out vaults if in min auction spacing time period
+    /// Points the client at a local server in tests
+    #[cfg(test)]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: Some(url.into()),
+            node: None,
+        }
+    }
+
+    /// Measures the subgraph's lag against a local node in tests
+    #[cfg(test)]
+    pub fn with_node(self, url: &str) -> Self {
+        Self {
+            node: Some(Provider::<Http>::try_from(url).unwrap()),
+            ..self
+        }
+    }
+
+    /// Latest block of the node the subgraph indexes
+    pub async fn chain_head(&self) -> Result<u64, eyre::Error> {
+        match &self.node {
+            Some(node) => Ok(node.get_block_number().await?.as_u64()),
+            None => latest_block_number().await,
+        }
+    }
+
+    /// Vaults over `debt_per_collateral` whose latest auction started before
+    /// `latest_auction_before_timestamp`, so auction spacing is respected.
     pub async fn collateral_vaults_exceeding_debt_per_collateral(
         &self,
         controller: &str,
         collateral: &str,
         debt_per_collateral: U256,
         latest_auction_before_timestamp: u64,
+        block: Option<u64>,
     ) -> Result<
         Vec<vaults_exceeding_debt_per_collateral::VaultsExceedingDebtPerCollateralVaults>,
         eyre::Error,
     > {
         use vaults_exceeding_debt_per_collateral::*;
-        let variables = Variables {
-            controller: Some(controller.to_string()),
-            collateral: Some(collateral.to_string()),
-            debt_per_collateral: Some(debt_per_collateral.to_string()),
-            latest_auction_before: Some(latest_auction_before_timestamp as i64),
-        };
-        let query = VaultsExceedingDebtPerCollateral::build_query(variables);
-        Ok(self.query::<_, ResponseData>(query).await?.vaults)
+        self.query_pages(
+            |last_id| {
+                VaultsExceedingDebtPerCollateral::build_query(Variables {
+                    controller: Some(controller.to_string()),
+                    collateral: Some(collateral.to_string()),
+                    debt_per_collateral: Some(debt_per_collateral.to_string()),
+                    // the filter is a 32 bit Int, u64::MAX means no spacing filter
+                    latest_auction_before: Some(
+                        latest_auction_before_timestamp.min(i32::MAX as u64) as i64,
+                    ),
+                    first: PAGE_SIZE,
+                    last_id,
+                    block: block.map(|number| Block_height {
+                        hash: None,
+                        number: Some(number as i64),
+                        number_gte: None,
+                    }),
+                })
+            },
+            |data: ResponseData| data.vaults,
+            |vault| &vault.id,
+        )
+        .await
     }
 
     // NOTE we do not filter on allowed collateral = true because there could be vaults
@@ -55,43 +165,203 @@ impl GraphQLClient {
     pub async fn collateral(
         &self,
         controller: &str,
+        block: Option<u64>,
     ) -> Result<Vec<collateral_by_controller::CollateralByControllerAllowedCollaterals>, eyre::Error>
     {
         use collateral_by_controller::*;
-        let variables = Variables {
-            controller: Some(controller.to_string()),
-        };
-        let query = CollateralByController::build_query(variables);
-        Ok(self
-            .query::<_, ResponseData>(query)
-            .await?
-            .allowed_collaterals)
+        self.query_pages(
+            |last_id| {
+                CollateralByController::build_query(Variables {
+                    controller: Some(controller.to_string()),
+                    first: PAGE_SIZE,
+                    last_id,
+                    block: block.map(|number| Block_height {
+                        hash: None,
+                        number: Some(number as i64),
+                        number_gte: None,
+                    }),
+                })
+            },
+            |data: ResponseData| data.allowed_collaterals,
+            |collateral| &collateral.id,
+        )
+        .await
     }
 
-    pub async fn all_papr_controllers(
+    pub async fn controller(
         &self,
-    ) -> Result<Vec<all_controllers::AllControllersPaprControllers>, eyre::Error> {
-        use all_controllers::*;
-        let query = AllControllers::build_query(Variables);
-        Ok(self.query::<_, ResponseData>(query).await?.papr_controllers)
+        id: &str,
+        block: Option<u64>,
+    ) -> Result<Option<controller_by_id::ControllerByIdPaprController>, eyre::Error> {
+        use controller_by_id::*;
+        let query = ControllerByID::build_query(Variables {
+            id: id.to_string(),
+            block: block.map(|number| Block_height {
+                hash: None,
+                number: Some(number as i64),
+                number_gte: None,
+            }),
+        });
+        Ok(self.query::<_, ResponseData>(query).await?.papr_controller)
     }
 
-    pub async fn ongoing_auctions(&self, controller: &str) -> Result<Vec<Auctions>, eyre::Error> {
+    pub async fn ongoing_auctions(
+        &self,
+        controller: &str,
+        block: Option<u64>,
+    ) -> Result<Vec<Auctions>, eyre::Error> {
         use ongoing_auctions_by_controller::*;
-        let variables = Variables {
-            controller: Some(controller.to_string()),
-        };
-        let query = OngoingAuctionsByController::build_query(variables);
-        Ok(self.query::<_, ResponseData>(query).await?.auctions)
+        self.query_pages(
+            |last_id| {
+                OngoingAuctionsByController::build_query(Variables {
+                    controller: Some(controller.to_string()),
+                    first: PAGE_SIZE,
+                    last_id,
+                    block: block.map(|number| Block_height {
+                        hash: None,
+                        number: Some(number as i64),
+                        number_gte: None,
+                    }),
+                })
+            },
+            |data: ResponseData| data.auctions,
+            |auction| &auction.id,
+        )
+        .await
+    }
+
+    pub async fn indexing_status(&self) -> Result<IndexingStatus, eyre::Error> {
+        use subgraph_meta::*;
+        let meta = self
+            .query::<_, ResponseData>(SubgraphMeta::build_query(Variables))
+            .await?
+            .meta
+            .ok_or(SubgraphError::MissingData)?;
+        Ok(IndexingStatus {
+            block_number: meta.block.number as u64,
+            has_indexing_errors: meta.has_indexing_errors,
+        })
+    }
+
+    /// Runs a list query page by page, ordered by id and starting each page after
+    /// the last id of the previous one, until a page comes back short.
+    async fn query_pages<V, D, T>(
+        &self,
+        build_query: impl Fn(String) -> QueryBody<V>,
+        items: impl Fn(D) -> Vec<T>,
+        id: impl Fn(&T) -> &str,
+    ) -> Result<Vec<T>, eyre::Error>
+    where
+        V: Serialize,
+        D: DeserializeOwned,
+    {
+        let mut all = vec![];
+        let mut last_id = String::new();
+        loop {
+            let page = items(self.query::<_, D>(build_query(last_id)).await?);
+            let complete = page.len() < PAGE_SIZE as usize;
+            last_id = match page.last() {
+                Some(item) => id(item).to_string(),
+                None => return Ok(all),
+            };
+            all.extend(page);
+            if complete {
+                return Ok(all);
+            }
+        }
     }
 
     async fn query<V: Serialize, D: DeserializeOwned>(
         &self,
         query: QueryBody<V>,
     ) -> Result<D, eyre::Error> {
-        let response = self.client.post(&*SUBGRAPH_URL).json(&query).send().await?;
+        let url = match &self.url {
+            Some(url) => url.as_str(),
+            None => SUBGRAPH_URL.as_str(),
+        };
+        let response = self.client.post(url).json(&query).send().await?;
         let body: Response<D> = response.json().await?;
-        body.data
-            .ok_or(eyre::eyre!("missing response data for query"))
+        if let Some(errors) = body.errors.filter(|errors| !errors.is_empty()) {
+            return Err(SubgraphError::GraphQL(errors).into());
+        }
+        Ok(body.data.ok_or(SubgraphError::MissingData)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mock_server::MockServer,
+        papr_subgraph::client::{GraphQLClient, IndexingStatus, PAGE_SIZE},
+    };
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn query_pages_until_a_short_page() {
+        let server = MockServer::start(|request| {
+            let body = request.json();
+            let last_id = body["variables"]["lastId"].as_str().unwrap().to_string();
+            // a full first page then one more item
+            let count = if last_id.is_empty() { PAGE_SIZE } else { 1 };
+            let offset = if last_id.is_empty() { 0 } else { PAGE_SIZE };
+            let collateral: Vec<_> = (offset..offset + count)
+                .map(|i| json!({ "id": format!("{:05}", i), "token": { "id": "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d" } }))
+                .collect();
+            Some(json!({ "data": { "allowedCollaterals": collateral } }))
+        });
+        let collateral = GraphQLClient::new(server.url())
+            .collateral("0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0", Some(16508300))
+            .await
+            .unwrap();
+
+        assert_eq!(collateral.len(), PAGE_SIZE as usize + 1);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method, "POST");
+        let second = requests[1].json();
+        assert_eq!(second["variables"]["lastId"], "00999");
+        assert_eq!(second["variables"]["block"]["number"], 16508300);
+    }
+
+    #[tokio::test]
+    async fn query_surfaces_subgraph_errors() {
+        let server = MockServer::start(|_| {
+            Some(json!({
+                "errors": [{ "message": "indexing_error" }]
+            }))
+        });
+        let err = GraphQLClient::new(server.url())
+            .indexing_status()
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            "subgraph errors: <query>:0:0: indexing_error",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn ensure_healthy_allows_lag_up_to_max() {
+        let status = IndexingStatus {
+            block_number: 100,
+            has_indexing_errors: false,
+        };
+        assert!(status.ensure_healthy(110, 10).is_ok());
+        // the node we ask for the chain head can itself be behind the subgraph
+        assert!(status.ensure_healthy(90, 10).is_ok());
+        assert_eq!(
+            "subgraph is 11 blocks behind the chain, at most 10 allowed",
+            status.ensure_healthy(111, 10).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn ensure_healthy_rejects_indexing_errors() {
+        let status = IndexingStatus {
+            block_number: 100,
+            has_indexing_errors: true,
+        };
+        assert!(status.ensure_healthy(100, 10).is_err());
     }
 }
@@ -19,11 +19,11 @@ pub struct CollateralByController;
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "src/papr_subgraph/graphql/paprSchema.graphql",
-    query_path = "src/papr_subgraph/graphql/allControllers.graphql"
+    query_path = "src/papr_subgraph/graphql/controllerByID.graphql"
 )]
-pub struct AllControllers;
+pub struct ControllerByID;
 
-impl all_controllers::AllControllersPaprControllers {
+impl controller_by_id::ControllerByIdPaprController {
     pub fn max_ltv_as_u256(&self) -> Result<U256, eyre::Error> {
         Ok(U256::from_dec_str(&self.max_ltv)?)
     }
@@ -35,3 +35,184 @@ impl all_controllers::AllControllersPaprControllers {
     query_path = "src/papr_subgraph/graphql/ongoingAuctionsByController.graphql"
 )]
 pub struct OngoingAuctionsByController;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/papr_subgraph/graphql/paprSchema.graphql",
+    query_path = "src/papr_subgraph/graphql/subgraphMeta.graphql"
+)]
+pub struct SubgraphMeta;
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser::{
+        query::{self, Selection, SelectionSet, Value},
+        schema::{self, InputValue, Type, TypeDefinition},
+    };
+    use std::{collections::HashMap, fs};
+
+    const GRAPHQL_DIR: &str = "src/papr_subgraph/graphql";
+
+    type Types<'a> = HashMap<&'a str, TypeDefinition<'a, &'a str>>;
+
+    /// declared type of each of a query's variables
+    type Variables<'a> = HashMap<&'a str, Type<'a, &'a str>>;
+
+    /// graphql_client only checks the fields a query selects, so unknown arguments
+    /// and filters, which the subgraph silently ignores, slip through.
+    #[test]
+    fn queries_are_valid_against_schema() {
+        let schema_source =
+            fs::read_to_string(format!("{}/paprSchema.graphql", GRAPHQL_DIR)).unwrap();
+        let schema = schema::parse_schema::<&str>(&schema_source).unwrap();
+        let types: Types = schema
+            .definitions
+            .into_iter()
+            .filter_map(|definition| match definition {
+                schema::Definition::TypeDefinition(t) => Some((type_name(&t), t)),
+                _ => None,
+            })
+            .collect();
+
+        let mut errors = vec![];
+        for entry in fs::read_dir(GRAPHQL_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("graphql".as_ref()) || path.ends_with("paprSchema.graphql")
+            {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            let document = query::parse_query::<&str>(&source).unwrap();
+            for definition in &document.definitions {
+                let query::Definition::Operation(query::OperationDefinition::Query(q)) = definition
+                else {
+                    errors.push(format!("{}: only queries are supported", path.display()));
+                    continue;
+                };
+                let variables: Variables = q
+                    .variable_definitions
+                    .iter()
+                    .map(|v| (v.name, v.var_type.clone()))
+                    .collect();
+                validate_selection_set(&types, &variables, "Query", &q.selection_set, &mut |e| {
+                    errors.push(format!("{}: {}", path.display(), e))
+                });
+            }
+        }
+        assert!(errors.is_empty(), "{}", errors.join("\n"));
+    }
+
+    fn type_name<'a>(definition: &TypeDefinition<'a, &'a str>) -> &'a str {
+        match definition {
+            TypeDefinition::Scalar(t) => t.name,
+            TypeDefinition::Object(t) => t.name,
+            TypeDefinition::Interface(t) => t.name,
+            TypeDefinition::Union(t) => t.name,
+            TypeDefinition::Enum(t) => t.name,
+            TypeDefinition::InputObject(t) => t.name,
+        }
+    }
+
+    fn named_type<'a>(t: &Type<'a, &'a str>) -> &'a str {
+        match t {
+            Type::NamedType(name) => name,
+            Type::ListType(inner) | Type::NonNullType(inner) => named_type(inner),
+        }
+    }
+
+    /// Whether a variable of type `variable` can be passed where `input` is
+    /// expected, ignoring default values
+    fn assignable<'v, 'i>(variable: &Type<'v, &'v str>, input: &Type<'i, &'i str>) -> bool {
+        match (variable, input) {
+            (Type::NonNullType(variable), Type::NonNullType(input)) => assignable(variable, input),
+            (_, Type::NonNullType(_)) => false,
+            (Type::NonNullType(variable), input) => assignable(variable, input),
+            (Type::ListType(variable), Type::ListType(input)) => assignable(variable, input),
+            (Type::NamedType(variable), Type::NamedType(input)) => variable == input,
+            _ => false,
+        }
+    }
+
+    fn validate_selection_set<'q>(
+        types: &Types,
+        variables: &Variables<'q>,
+        parent: &str,
+        selection_set: &SelectionSet<'q, &'q str>,
+        error: &mut dyn FnMut(String),
+    ) {
+        let fields = match types.get(parent) {
+            Some(TypeDefinition::Object(t)) => &t.fields,
+            Some(TypeDefinition::Interface(t)) => &t.fields,
+            _ => return error(format!("{} has no fields to select", parent)),
+        };
+        for selection in &selection_set.items {
+            let Selection::Field(field) = selection else {
+                error(format!("fragments are not supported in {}", parent));
+                continue;
+            };
+            if field.name == "__typename" {
+                continue;
+            }
+            let Some(definition) = fields.iter().find(|f| f.name == field.name) else {
+                error(format!("unknown field {} on {}", field.name, parent));
+                continue;
+            };
+            for (name, value) in &field.arguments {
+                match definition.arguments.iter().find(|a| a.name == *name) {
+                    Some(argument) => validate_value(types, variables, argument, value, error),
+                    None => error(format!(
+                        "unknown argument {} on {}.{}",
+                        name, parent, field.name
+                    )),
+                }
+            }
+            // the subgraph silently truncates lists to the default page size
+            if definition.arguments.iter().any(|a| a.name == "first")
+                && !field.arguments.iter().any(|(name, _)| *name == "first")
+            {
+                error(format!("{}.{} needs an explicit first", parent, field.name));
+            }
+            if !field.selection_set.items.is_empty() {
+                validate_selection_set(
+                    types,
+                    variables,
+                    named_type(&definition.field_type),
+                    &field.selection_set,
+                    error,
+                );
+            }
+        }
+    }
+
+    fn validate_value<'s, 'q>(
+        types: &Types<'s>,
+        variables: &Variables<'q>,
+        input: &InputValue<'s, &'s str>,
+        value: &Value<'q, &'q str>,
+        error: &mut dyn FnMut(String),
+    ) {
+        match value {
+            Value::Variable(name) => match variables.get(name) {
+                None => error(format!("undeclared variable ${}", name)),
+                Some(declared) if !assignable(declared, &input.value_type) => error(format!(
+                    "variable ${} is declared {} but {} expects {}",
+                    name, declared, input.name, input.value_type
+                )),
+                Some(_) => {}
+            },
+            Value::Object(fields) => {
+                let type_name = named_type(&input.value_type);
+                let Some(TypeDefinition::InputObject(input_type)) = types.get(type_name) else {
+                    return error(format!("{} is not an input object", type_name));
+                };
+                for (name, value) in fields {
+                    match input_type.fields.iter().find(|f| f.name == *name) {
+                        Some(field) => validate_value(types, variables, field, value, error),
+                        None => error(format!("unknown filter {} on {}", name, type_name)),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
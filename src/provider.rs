@@ -1,9 +1,10 @@
+use crate::nonce::NonceManager;
 use ethers::{
     core::k256::ecdsa::SigningKey,
     middleware::SignerMiddleware,
-    providers::{Http, Provider},
+    providers::{Http, Middleware, Provider},
     signers::{LocalWallet, Signer, Wallet},
-    types::U256,
+    types::{BlockNumber, U256},
 };
 use once_cell::sync::Lazy;
 use std::{env, sync::Arc};
@@ -28,3 +29,27 @@ pub static PROVIDER: Lazy<Arc<SignerMiddleware<Provider<Http>, Wallet<SigningKey
 
         Arc::new(SignerMiddleware::new(provider, wallet))
     });
+
+/// Nonces of `PROVIDER`'s signer
+pub static PROVIDER_NONCES: Lazy<Arc<NonceManager>> = Lazy::new(Default::default);
+
+pub async fn latest_block_timestamp() -> Result<u64, eyre::Error> {
+    Ok(latest_block().await?.1)
+}
+
+/// Number and timestamp of the latest block, for reads that must agree with
+/// each other on the block
+pub async fn latest_block() -> Result<(u64, u64), eyre::Error> {
+    let block = PROVIDER
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or(eyre::eyre!("latest block not found"))?;
+    let number = block
+        .number
+        .ok_or(eyre::eyre!("latest block has no number"))?;
+    Ok((number.as_u64(), block.timestamp.as_u64()))
+}
+
+pub async fn latest_block_number() -> Result<u64, eyre::Error> {
+    Ok(PROVIDER.get_block_number().await?.as_u64())
+}
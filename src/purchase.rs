@@ -1,125 +1,925 @@
 use crate::{
-    papr_subgraph::client::GraphQLClient,
-    papr_subgraph::queries::{
-        all_controllers::AllControllersPaprControllers as Controller,
-        ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as SubgraphAuction,
+    config::{Config, ControllerConfig},
+    journal::{Decision, Journal, PurchaseDecision},
+    papr_controller::{Auction, PurchaseExecutor},
+    papr_subgraph::queries::ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as SubgraphAuction,
+    reservoir::{
+        oracle::{MessageValidity, PriceKind, PriceOracle},
+        orders::NetAmount,
+        sell::Marketplace,
     },
-    reservoir::{client::ReservoirClient},
+    vault_source::{AuctionSource, Controller},
+    wad,
 };
 use ethers::{
-    types::U256,
+    types::{Address, I256, U256},
     utils::{format_units, parse_units},
 };
-use once_cell::sync::Lazy;
-use std::{
-    collections::HashSet,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Rough gas to buy the papr, purchase an auction and sell the NFT, including
+/// approvals
+const PURCHASE_AND_SELL_GAS: u64 = 750_000;
+
+/// Per controller state shared by each auction's purchase
+struct PurchaseContext<'a, E> {
+    controller: &'a Controller,
+    config: &'a ControllerConfig,
+    journal: &'a Journal,
+    provider: &'a E,
+    address: Address,
+    papr: Address,
+    underlying: Address,
+    pool: Address,
+    creator_discount: U256,
+    /// block the auctions were read at
+    snapshot_block: u64,
+}
 
-// goerli
-pub static WHITELIST: Lazy<HashSet<&'static str>> = Lazy::new(|| {
-    let mut m = HashSet::new();
-    // goerli paprHero
-    m.insert("0xd0a830278773282bbf635fd8e47b2447f1e9fe86");
-    // paprMeme
-    m.insert("0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0");
-    m
-});
-
-pub async fn purchase_auctions_from_whitelisted_controllers(
-    reservoir: &ReservoirClient,
-    graphql: &GraphQLClient,
+/// Buying an auction with papr bought from Uniswap and selling the NFT into the
+/// best bid, all amounts in the controller's underlying.
+#[derive(Debug, PartialEq)]
+struct Opportunity {
+    cost: U256,
+    proceeds: U256,
+    gas_cost: U256,
+}
+
+impl Opportunity {
+    /// `None` unless the bid covers the papr and gas
+    fn profit(&self) -> Option<U256> {
+        self.proceeds
+            .checked_sub(self.cost)?
+            .checked_sub(self.gas_cost)
+    }
+}
+
+/// Evaluates, and with `execute` purchases, the ongoing auctions of every
+/// controller configured for purchasing. `executor` builds the controller at
+/// an address.
+pub async fn purchase_auctions_from_whitelisted_controllers<E: PurchaseExecutor>(
+    marketplace: &(impl PriceOracle + Marketplace),
+    auctions: &impl AuctionSource,
+    executor: impl Fn(&str) -> Result<E, eyre::Error>,
+    config: &Config,
+    journal: &Journal,
+    execute: bool,
 ) -> Result<(), eyre::Error> {
-    let controllers = graphql.all_papr_controllers().await.unwrap();
+    let snapshot_block = auctions
+        .snapshot_block(config.max_subgraph_lag_blocks)
+        .await?;
+    println!("reading auctions at block {}", snapshot_block);
 
-    for controller in controllers {
-        if WHITELIST.contains(&*controller.id) {
-            arb_auctions_for_controller(controller, reservoir, graphql).await?;
+    for controller_config in config.purchase_controllers() {
+        println!(
+            "purchasing for {} {}",
+            controller_config.name.as_deref().unwrap_or(""),
+            controller_config.address
+        );
+        // config addresses are validated so this cannot fail
+        let provider = executor(&controller_config.address)?;
+        if let Err(err) = arb_auctions_for_controller(
+            controller_config,
+            journal,
+            marketplace,
+            auctions,
+            &provider,
+            snapshot_block,
+            execute,
+        )
+        .await
+        {
+            println!(
+                "error purchasing from controller {}: {}",
+                controller_config.address, err
+            );
         }
     }
     Ok(())
 }
 
 async fn arb_auctions_for_controller(
-    controller: Controller,
-    reservoir: &ReservoirClient,
-    graphql: &GraphQLClient,
+    config: &ControllerConfig,
+    journal: &Journal,
+    marketplace: &(impl PriceOracle + Marketplace),
+    auctions: &impl AuctionSource,
+    provider: &impl PurchaseExecutor,
+    snapshot_block: u64,
+    execute: bool,
+) -> Result<(), eyre::Error> {
+    let controller = auctions.controller(&config.address, snapshot_block).await?;
+    let context = PurchaseContext {
+        controller: &controller,
+        config,
+        journal,
+        provider,
+        address: config.address.parse()?,
+        papr: provider.papr().await?,
+        underlying: provider.underlying().await?,
+        pool: provider.pool().await?,
+        creator_discount: provider.auction_creator_discount_percent_wad().await?,
+        snapshot_block,
+    };
+    let auctions = auctions
+        .ongoing_auctions(&controller.id, snapshot_block)
+        .await?;
+    println!("found {} ongoing auctions", auctions.len());
+    // NOTE buying papr, purchasing and selling are separate transactions so the bot
+    // needs an underlying balance and carries the NFT in between. Doing it atomically needs
+    // a contract that swaps papr from uniswap and purchases and sells in the callback
+    for auction in &auctions {
+        if !config.collateral_enabled(&auction.auction_asset_contract.id) {
+            continue;
+        }
+        let decision = match arb_auction(&context, auction, marketplace, execute).await {
+            Ok(decision) => decision,
+            Err(err) => {
+                println!("error purchasing auction {}: {}", auction.id, err);
+                PurchaseDecision {
+                    error: Some(err.to_string()),
+                    ..decision(&context, auction, Decision::Failed)?
+                }
+            }
+        };
+        journal.record_purchase(&decision)?;
+    }
+    Ok(())
+}
+
+/// A decision about `auction` before any amounts are known
+fn decision<E>(
+    context: &PurchaseContext<'_, E>,
+    auction: &SubgraphAuction,
+    decision: Decision,
+) -> Result<PurchaseDecision, eyre::Error> {
+    Ok(PurchaseDecision {
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        controller: context.controller.id.clone(),
+        auction_id: U256::from_dec_str(&auction.id)?,
+        collection: auction.auction_asset_contract.id.clone(),
+        token_id: U256::from_dec_str(&auction.auction_asset_id)?,
+        snapshot_block: context.snapshot_block,
+        price: None,
+        cost: None,
+        proceeds: None,
+        gas_cost: None,
+        decision,
+        error: None,
+    })
+}
+
+async fn arb_auction(
+    context: &PurchaseContext<'_, impl PurchaseExecutor>,
+    auction: &SubgraphAuction,
+    marketplace: &(impl PriceOracle + Marketplace),
+    execute: bool,
+) -> Result<PurchaseDecision, eyre::Error> {
+    let collection = &auction.auction_asset_contract.id;
+    let token_id = U256::from_dec_str(&auction.auction_asset_id)?;
+    let (block, timestamp) = context.provider.latest_block().await?;
+    let mut price = current_price(auction, timestamp)?;
+    if started_by_us(context, auction)? {
+        price = apply_discount(price, context.creator_discount);
+    }
+    check_current_price(context, auction, price, block).await?;
+    let cost = context
+        .provider
+        .quote_exact_output(context.pool, context.underlying, context.papr, price)
+        .await?;
+    let priced = PurchaseDecision {
+        timestamp,
+        price: Some(price),
+        cost: Some(cost),
+        ..decision(context, auction, Decision::NoBid)?
+    };
+
+    let bot = format!("{:?}", context.provider.bot());
+    let quote = marketplace.sell_token(collection, token_id, bot).await?;
+    let Some(path) = quote.path.first() else {
+        println!("no bids for {} id {}", collection, token_id);
+        return Ok(priced);
+    };
+    let order = marketplace
+        .bids_by_ids(std::slice::from_ref(&path.order_id))
+        .await?
+        .orders
+        .into_iter()
+        .find(|order| order.id == path.order_id)
+        .ok_or(eyre::eyre!("bid {} not found", path.order_id))?;
+    println!(
+        "best bid for {} id {} is a {} {} bid",
+        collection, token_id, order.criteria.kind, order.kind
+    );
+    if order.price.currency.contract.parse::<Address>()? != context.underlying {
+        println!(
+            "bid currency {} is not the underlying",
+            order.price.currency.contract
+        );
+        return Ok(priced);
+    }
+    let gas_price = context.provider.gas_price().await?;
+    let opportunity = Opportunity {
+        cost,
+        proceeds: U256::from_dec_str(&order.price.net_amount.raw)?,
+        gas_cost: gas_cost_in_currency(
+            gas_price * PURCHASE_AND_SELL_GAS,
+            &order.price.net_amount,
+            order.price.currency.decimals,
+        )?,
+    };
+    println!(
+        "auction {} price {} papr costs {} bid nets {} gas {}",
+        auction.id, price, opportunity.cost, opportunity.proceeds, opportunity.gas_cost
+    );
+    let evaluated = PurchaseDecision {
+        proceeds: Some(opportunity.proceeds),
+        gas_cost: Some(opportunity.gas_cost),
+        ..priced
+    };
+    let Some(profit) = opportunity.profit() else {
+        println!("auction {} not profitable", auction.id);
+        return Ok(PurchaseDecision {
+            decision: Decision::Unprofitable,
+            ..evaluated
+        });
+    };
+    println!("auction {} profit {}", auction.id, profit);
+    if !execute {
+        return Ok(PurchaseDecision {
+            decision: Decision::Profitable,
+            ..evaluated
+        });
+    }
+    Ok(
+        match purchase_and_sell(context, auction, token_id, price, cost, marketplace).await {
+            Ok(()) => PurchaseDecision {
+                decision: Decision::Purchased,
+                ..evaluated
+            },
+            Err(err) => {
+                println!("error purchasing auction {}: {}", auction.id, err);
+                PurchaseDecision {
+                    decision: Decision::Failed,
+                    error: Some(err.to_string()),
+                    ..evaluated
+                }
+            }
+        },
+    )
+}
+
+async fn purchase_and_sell(
+    context: &PurchaseContext<'_, impl PurchaseExecutor>,
+    auction: &SubgraphAuction,
+    token_id: U256,
+    price: U256,
+    cost: U256,
+    marketplace: &(impl PriceOracle + Marketplace),
 ) -> Result<(), eyre::Error> {
-    let auctions = graphql.ongoing_auctions(&controller.id);
-    // niave: for each auction, better to cache reservoir responses for a given NFT contract
-    //  1. get current_price 
-    //  2. quote from uniswap on how much ETH to buy papr
-    //  3. call reservoir::sell
-    //  4. get orderId from path
-    //  5. get order from reservoir::orders::bids (need to update to take optional arg of orderId array)
-    //  6. check order.price.net_amount > required ETH
-    //  7. Call a multicall contract: swap papr from uniswap and encode following steps in the callback data
-    //     - call purchase auction (ensure papr controller approved to pull WETH from contract)
-    //     - all steps from reservoir::sell
-    //     - send needed WETH proceeds (wrap if needed) to uniswap 
-    //     - sanity check that ending ETH > starting ETH :) 
-
-    // NOTE to take advantage of starter incentive you'd need to make sure the purchase contract also starts
-    // would want to keep track of which auctions you started so that you can update current_price correctly (-10%)
+    let collection = &auction.auction_asset_contract.id;
+    let bot = context.provider.bot();
+    let balance = context.provider.balance_of(context.underlying).await?;
+    if balance < cost {
+        return Err(eyre::eyre!(
+            "underlying balance {} below papr cost {}",
+            balance,
+            cost
+        ));
+    }
+
+    let oracle_response = marketplace
+        .max_collection_bid(
+            collection,
+            PriceKind::Twap,
+            &context.controller.underlying,
+            Some(context.config.twap_seconds),
+        )
+        .await?;
+    oracle_response
+        .message
+        .ensure_signed_by(context.provider.oracle_signer().await?)?;
+    oracle_response.signed_price(
+        context.underlying,
+        context.controller.underlying_decimals as u32,
+    )?;
+    let (_, block_timestamp) = context.provider.latest_block().await?;
+    let validity = oracle_response.message.validity(block_timestamp);
+    if validity != MessageValidity::Valid {
+        return Err(eyre::eyre!("oracle message not usable: {:?}", validity));
+    }
+
+    // buy the papr at no more than the cost the profit was computed with
+    let paid = context
+        .provider
+        .swap_exact_output(context.pool, context.underlying, context.papr, price, cost)
+        .await?;
+    println!("bought {} papr for {}", price, paid);
+    context
+        .provider
+        .ensure_allowance(context.papr, context.address, price)
+        .await?;
 
+    let receipt = context
+        .provider
+        .purchase_liquidation_auction_nft(
+            contract_auction(auction)?,
+            price,
+            bot,
+            oracle_response.message.as_contract_oracle_info()?,
+        )
+        .await?;
+    if receipt.status != Some(1.into()) {
+        return Err(eyre::eyre!(
+            "purchase transaction {:?} reverted",
+            receipt.transaction_hash
+        ));
+    }
+    println!(
+        "purchased auction {} in {:?}",
+        auction.id, receipt.transaction_hash
+    );
+
+    // now that we own the NFT the marketplace returns the steps to sell it
+    let sale = marketplace
+        .sell_token(collection, token_id, format!("{:?}", bot))
+        .await?;
+    for step in &sale.steps {
+        for item in step.items.iter().filter(|item| item.is_incomplete()) {
+            if !step.is_transaction() {
+                return Err(eyre::eyre!("sell step {} needs a signature", step.id));
+            }
+            let tx = item
+                .data
+                .as_ref()
+                .ok_or(eyre::eyre!("sell step {} missing transaction", step.id))?
+                .as_transaction()?;
+            let receipt = context.provider.send_transaction(tx).await?;
+            println!("sell step {} in {:?}", step.id, receipt.transaction_hash);
+        }
+    }
     Ok(())
 }
 
-fn current_price(auction: SubgraphAuction) -> Result<U256, eyre::Error> {
-    let start_price = format_units(
-        U256::from_dec_str(&auction.start_price)?,
-        auction.payment_asset.decimals as u32,
-    )?
-    .parse::<f64>()?;
-    let decay: f64 = format_units(
-        U256::from_dec_str(&auction.per_period_decay_percent_wad)?,
-        18,
-    )?
-    .parse::<f64>()?;
-
-    let elapsed_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)?
-        .as_secs()
-        .checked_sub(auction.start.timestamp as u64)
-        .ok_or(eyre::eyre!("timestamp error"))?;
+/// Compares our price at `block` with the controller's `auctionCurrentPrice`
+/// at the same block, which is discounted if we started the auction,
+/// reporting any discrepancy.
+async fn check_current_price(
+    context: &PurchaseContext<'_, impl PurchaseExecutor>,
+    auction: &SubgraphAuction,
+    price: U256,
+    block: u64,
+) -> Result<(), eyre::Error> {
+    let on_chain = context
+        .provider
+        .auction_current_price(contract_auction(auction)?, block)
+        .await?;
+    if on_chain != price {
+        println!(
+            "auction {} price {} differs from on chain price {}",
+            auction.id, price, on_chain
+        );
+    }
+    Ok(())
+}
 
-    let period_ratio = elapsed_time as f64 / auction.seconds_in_period.parse::<f64>()?;
-    let percent_remaining = 1 as f64 - decay;
-    let multiplier = percent_remaining.powf(period_ratio);
-    let price = multiplier * start_price;
+/// Auctions we started are sold to us at the auction creator discount
+fn started_by_us(
+    context: &PurchaseContext<'_, impl PurchaseExecutor>,
+    auction: &SubgraphAuction,
+) -> Result<bool, eyre::Error> {
+    Ok(
+        auction.started_by.to_string().parse::<Address>()? == context.provider.bot()
+            || context
+                .journal
+                .started_auction_id(U256::from_dec_str(&auction.id)?)?,
+    )
+}
 
-    Ok(parse_units(price, auction.payment_asset.decimals as u32)?.into())
+/// Same rounding as the controller, which rounds the discounted price up
+fn apply_discount(price: U256, discount_percent_wad: U256) -> U256 {
+    wad::mul_wad_up(price, U256::from(wad::WAD) - discount_percent_wad)
 }
 
-// get all ongoing auctions
-// compute current price for each
-// convert papr price -> underlying price using uniswap
-// (probably just use tick and later check slippage inclusive price?)
-// try to find bids above that price
-// swap -> on callback, purchase NFT and sell -> send funds owed back to Uniswap
+/// Converts a gas cost in wei into the bid's currency at the exchange rate
+/// implied by the bid's native and decimal amounts.
+fn gas_cost_in_currency(
+    gas_cost_wei: U256,
+    net_amount: &NetAmount,
+    decimals: u8,
+) -> Result<U256, eyre::Error> {
+    if net_amount.native <= 0.0 {
+        return Err(eyre::eyre!("bid has no native price"));
+    }
+    let gas_cost_eth = format_units(gas_cost_wei, "ether")?.parse::<f64>()?;
+    let cost = gas_cost_eth * net_amount.decimal / net_amount.native;
+    Ok(parse_units(format!("{:.*}", decimals as usize, cost), decimals as u32)?.into())
+}
+
+fn contract_auction(auction: &SubgraphAuction) -> Result<Auction, eyre::Error> {
+    Ok(Auction {
+        nft_owner: auction.nft_owner.to_string().parse()?,
+        auction_asset_id: U256::from_dec_str(&auction.auction_asset_id)?,
+        auction_asset_contract: auction.auction_asset_contract.id.parse()?,
+        per_period_decay_percent_wad: U256::from_dec_str(&auction.per_period_decay_percent_wad)?,
+        seconds_in_period: U256::from_dec_str(&auction.seconds_in_period)?,
+        start_price: U256::from_dec_str(&auction.start_price)?,
+        payment_asset: auction.payment_asset.id.parse()?,
+    })
+}
+
+/// The auction's price at `timestamp` before any auction creator discount,
+/// computed the way the controller's `auctionCurrentPrice` does.
+fn current_price(auction: &SubgraphAuction, timestamp: u64) -> Result<U256, eyre::Error> {
+    let start_price = U256::from_dec_str(&auction.start_price)?;
+    let decay = U256::from_dec_str(&auction.per_period_decay_percent_wad)?;
+    let elapsed_time = timestamp
+        .checked_sub(auction.start.timestamp as u64)
+        .ok_or(eyre::eyre!("timestamp before auction start"))?;
+
+    let period_ratio = wad::div_wad_down(
+        U256::from(elapsed_time),
+        U256::from_dec_str(&auction.seconds_in_period)?,
+    );
+    let percent_remaining = U256::from(wad::WAD) - decay;
+    let multiplier = wad::pow_wad(
+        I256::try_from(percent_remaining)?,
+        I256::try_from(period_ratio)?,
+    )?;
+    Ok(wad::mul_wad_down(start_price, multiplier.into_raw()))
+}
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        config::{Config, ControllerConfig},
+        journal::{Decision, Entry, Journal},
+        local_node::sign_price,
+        papr_controller::{Auction, OracleInfo, PurchaseExecutor},
         papr_subgraph::queries::{
             ongoing_auctions_by_controller,
             ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as SubgraphAuction,
         },
-        purchase::current_price
+        purchase::{
+            apply_discount, arb_auction, current_price, gas_cost_in_currency,
+            purchase_auctions_from_whitelisted_controllers, Opportunity, PurchaseContext,
+        },
+        reservoir::{
+            oracle::{OracleResponse, PriceKind, PriceOracle},
+            orders::{BidsResponse, NetAmount},
+            sell::{self, Marketplace},
+        },
+        vault_source::{AuctionSource, Controller, Vault, VaultSource},
+    };
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip2718::TypedTransaction, Address, Bytes, TransactionReceipt, U256},
     };
-    use ethers::types::{Bytes, U256};
-    use std::str::FromStr;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::{
+        env, fs, process,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+    };
+
+    const NOW: u64 = 1674959723;
+    const BLOCK: u64 = 16508300;
+    const CONTROLLER: &str = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0";
+    /// the collection and token the recorded Reservoir sale is for
+    const COLLECTION: &str = "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d";
+    const TOKEN_ID: u64 = 7;
+
+    #[tokio::test]
+    async fn arb_auction_purchases_and_sells_profitable_auction() {
+        let (journal, path) = journal("purchased");
+        let provider = FakePurchaser::default();
+        let auction = auction(60, Address::zero());
+        let decision = arb_auction(
+            &context(&controller(), &config(), &journal, &provider),
+            &auction,
+            &FakeMarket::default(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        let price = current_price(&auction, NOW).unwrap();
+        assert_eq!(decision.decision, Decision::Purchased);
+        assert_eq!(decision.price, Some(price));
+        // papr trades 1:1 and gas is 750k at 1 gwei
+        assert_eq!(decision.cost, Some(price));
+        assert_eq!(
+            decision.proceeds,
+            Some(U256::from(63_375) * U256::exp10(15))
+        );
+        assert_eq!(
+            decision.gas_cost,
+            Some(U256::from(750_000) * U256::exp10(9))
+        );
+        assert_eq!(decision.snapshot_block, BLOCK);
+        assert_eq!(*provider.purchased.lock().unwrap(), vec![price]);
+        // approving the collection and accepting the bid
+        assert_eq!(provider.sent.load(Ordering::SeqCst), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn arb_auction_only_evaluates_unless_executing() {
+        let (journal, path) = journal("evaluated");
+        let provider = FakePurchaser::default();
+        let (controller, config) = (controller(), config());
+        let context = context(&controller, &config, &journal, &provider);
+        let market = FakeMarket::default();
+
+        let profitable = arb_auction(&context, &auction(60, Address::zero()), &market, false)
+            .await
+            .unwrap();
+        assert_eq!(profitable.decision, Decision::Profitable);
+        let unprofitable = arb_auction(&context, &auction(64, Address::zero()), &market, false)
+            .await
+            .unwrap();
+        assert_eq!(unprofitable.decision, Decision::Unprofitable);
+        let no_bids = arb_auction(
+            &context,
+            &auction(60, Address::zero()),
+            &FakeMarket { no_bids: true },
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(no_bids.decision, Decision::NoBid);
+        assert_eq!(no_bids.proceeds, None);
+        assert!(provider.purchased.lock().unwrap().is_empty());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn arb_auction_discounts_auctions_we_started() {
+        let (journal, path) = journal("discount");
+        let provider = FakePurchaser::default();
+        let auction = auction(60, provider.bot());
+        let decision = arb_auction(
+            &context(&controller(), &config(), &journal, &provider),
+            &auction,
+            &FakeMarket::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let ten_percent = U256::exp10(17);
+        assert_eq!(
+            decision.price,
+            Some(apply_discount(
+                current_price(&auction, NOW).unwrap(),
+                ten_percent
+            ))
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn journals_decisions_for_controllers_configured_to_purchase() {
+        let (journal, path) = journal("whitelisted");
+        let config = Config::parse(&format!(
+            r#"
+            [[controllers]]
+            address = "{}"
+            purchase = true
+
+            [[controllers]]
+            address = "0xd0a830278773282bbf635fd8e47b2447f1e9fe86"
+            liquidate = true
+            "#,
+            CONTROLLER
+        ))
+        .unwrap();
+        let built = Mutex::new(vec![]);
+        purchase_auctions_from_whitelisted_controllers(
+            &FakeMarket::default(),
+            &FakeAuctions,
+            |address| {
+                built.lock().unwrap().push(address.to_string());
+                Ok(FakePurchaser::default())
+            },
+            &config,
+            &journal,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*built.lock().unwrap(), vec![CONTROLLER.to_string()]);
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        let Entry::Purchase(decision) = &entries[0] else {
+            panic!("expected a purchase decision");
+        };
+        assert_eq!(decision.decision, Decision::Profitable);
+        assert_eq!(decision.controller, CONTROLLER);
+        assert_eq!(decision.snapshot_block, BLOCK);
+        let _ = fs::remove_file(&path);
+    }
+
+    fn journal(name: &str) -> (Journal, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!(
+            "auction-bot-purchase-{}-{}.jsonl",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        (Journal::open(&path).unwrap(), path)
+    }
+
+    fn config() -> ControllerConfig {
+        Config::parse(&format!(
+            "[[controllers]]\naddress = \"{}\"\npurchase = true",
+            CONTROLLER
+        ))
+        .unwrap()
+        .controllers
+        .remove(0)
+    }
+
+    fn controller() -> Controller {
+        Controller {
+            id: CONTROLLER.to_string(),
+            max_ltv: U256::exp10(17) * 5,
+            underlying: format!("{:?}", weth()),
+            underlying_decimals: 18,
+        }
+    }
+
+    fn context<'a>(
+        controller: &'a Controller,
+        config: &'a ControllerConfig,
+        journal: &'a Journal,
+        provider: &'a FakePurchaser,
+    ) -> PurchaseContext<'a, FakePurchaser> {
+        PurchaseContext {
+            controller,
+            config,
+            journal,
+            provider,
+            address: CONTROLLER.parse().unwrap(),
+            papr: papr(),
+            underlying: weth(),
+            pool: Address::from_low_u64_be(3),
+            creator_discount: U256::exp10(17),
+            snapshot_block: BLOCK,
+        }
+    }
+
+    fn weth() -> Address {
+        "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+            .parse()
+            .unwrap()
+    }
+
+    fn papr() -> Address {
+        Address::from_low_u64_be(2)
+    }
+
+    /// Anvil account 0, the oracle signer of the fake controller
+    fn oracle_wallet() -> LocalWallet {
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .parse()
+            .unwrap()
+    }
+
+    /// An auction of the sold token that starts at `NOW` for `start_papr` papr
+    fn auction(start_papr: u64, started_by: Address) -> SubgraphAuction {
+        use ongoing_auctions_by_controller::*;
+        SubgraphAuction {
+            id: "42".into(),
+            nft_owner: Bytes::from_str("0xbc3ed6b537f2980e66f396fe14210a56ba3f72c4").unwrap(),
+            started_by: started_by.as_bytes().to_vec().into(),
+            auction_asset_id: TOKEN_ID.to_string(),
+            auction_asset_contract: OngoingAuctionsByControllerAuctionsAuctionAssetContract {
+                id: COLLECTION.into(),
+            },
+            vault: OngoingAuctionsByControllerAuctionsVault {
+                account: Bytes::from_str("0xbc3ed6b537f2980e66f396fe14210a56ba3f72c4").unwrap(),
+            },
+            seconds_in_period: "86400".into(),
+            start_price: (U256::from(start_papr) * U256::exp10(18)).to_string(),
+            per_period_decay_percent_wad: "700000000000000000".into(),
+            payment_asset: OngoingAuctionsByControllerAuctionsPaymentAsset {
+                id: format!("{:?}", papr()),
+                decimals: 18,
+            },
+            start: OngoingAuctionsByControllerAuctionsStart {
+                timestamp: NOW as i64,
+            },
+        }
+    }
+
+    /// Sells into the recorded Reservoir bid netting 63.375 WETH, with oracle
+    /// messages pricing every collection at 63 signed at `NOW`
+    #[derive(Default)]
+    struct FakeMarket {
+        no_bids: bool,
+    }
+
+    impl PriceOracle for FakeMarket {
+        async fn max_collection_bid(
+            &self,
+            collection: &str,
+            _price_kind: PriceKind,
+            quote_currency: &str,
+            _twap_seconds: Option<u32>,
+        ) -> Result<OracleResponse, eyre::Error> {
+            Ok(OracleResponse {
+                price: 63.0,
+                message: sign_price(
+                    &oracle_wallet(),
+                    collection.parse()?,
+                    quote_currency.parse()?,
+                    U256::from(63) * U256::exp10(18),
+                    NOW,
+                ),
+            })
+        }
+    }
+
+    impl Marketplace for FakeMarket {
+        async fn sell_token(
+            &self,
+            _collection: &str,
+            _token_id: U256,
+            _seller: String,
+        ) -> Result<sell::Response, eyre::Error> {
+            if self.no_bids {
+                return Ok(serde_json::from_str(r#"{ "steps": [] }"#)?);
+            }
+            Ok(serde_json::from_str(include_str!(
+                "fixtures/reservoir_sell.json"
+            ))?)
+        }
+
+        async fn bids_by_ids(&self, _ids: &[String]) -> Result<BidsResponse, eyre::Error> {
+            Ok(serde_json::from_str(include_str!(
+                "fixtures/reservoir_bids.json"
+            ))?)
+        }
+    }
+
+    /// A controller whose papr trades 1:1 against WETH with gas at 1 gwei,
+    /// recording the purchases and other transactions it sends
+    #[derive(Default)]
+    struct FakePurchaser {
+        purchased: Mutex<Vec<U256>>,
+        sent: AtomicUsize,
+    }
+
+    impl PurchaseExecutor for FakePurchaser {
+        fn bot(&self) -> Address {
+            Address::from_low_u64_be(0xb07)
+        }
+
+        async fn latest_block(&self) -> Result<(u64, u64), eyre::Error> {
+            Ok((BLOCK, NOW))
+        }
+
+        async fn gas_price(&self) -> Result<U256, eyre::Error> {
+            Ok(U256::exp10(9))
+        }
+
+        async fn papr(&self) -> Result<Address, eyre::Error> {
+            Ok(papr())
+        }
+
+        async fn underlying(&self) -> Result<Address, eyre::Error> {
+            Ok(weth())
+        }
+
+        async fn pool(&self) -> Result<Address, eyre::Error> {
+            Ok(Address::from_low_u64_be(3))
+        }
+
+        async fn auction_creator_discount_percent_wad(&self) -> Result<U256, eyre::Error> {
+            Ok(U256::exp10(17))
+        }
+
+        async fn oracle_signer(&self) -> Result<Address, eyre::Error> {
+            Ok(oracle_wallet().address())
+        }
+
+        async fn auction_current_price(
+            &self,
+            _auction: Auction,
+            _block: u64,
+        ) -> Result<U256, eyre::Error> {
+            Ok(U256::zero())
+        }
+
+        async fn balance_of(&self, _token: Address) -> Result<U256, eyre::Error> {
+            Ok(U256::from(100) * U256::exp10(18))
+        }
+
+        async fn quote_exact_output(
+            &self,
+            _pool: Address,
+            _token_in: Address,
+            _token_out: Address,
+            amount_out: U256,
+        ) -> Result<U256, eyre::Error> {
+            Ok(amount_out)
+        }
+
+        async fn swap_exact_output(
+            &self,
+            _pool: Address,
+            _token_in: Address,
+            _token_out: Address,
+            amount_out: U256,
+            _max_amount_in: U256,
+        ) -> Result<U256, eyre::Error> {
+            Ok(amount_out)
+        }
+
+        async fn ensure_allowance(
+            &self,
+            _token: Address,
+            _spender: Address,
+            _amount: U256,
+        ) -> Result<(), eyre::Error> {
+            Ok(())
+        }
+
+        async fn purchase_liquidation_auction_nft(
+            &self,
+            _auction: Auction,
+            max_price: U256,
+            _send_to: Address,
+            _oracle_info: OracleInfo,
+        ) -> Result<TransactionReceipt, eyre::Error> {
+            self.purchased.lock().unwrap().push(max_price);
+            Ok(TransactionReceipt {
+                status: Some(1.into()),
+                ..Default::default()
+            })
+        }
+
+        async fn send_transaction(
+            &self,
+            _tx: TypedTransaction,
+        ) -> Result<TransactionReceipt, eyre::Error> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            Ok(TransactionReceipt {
+                status: Some(1.into()),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Serves the fake controller with one ongoing auction
+    struct FakeAuctions;
+
+    impl VaultSource for FakeAuctions {
+        async fn snapshot_block(&self, _max_lag_blocks: u64) -> Result<u64, eyre::Error> {
+            Ok(BLOCK)
+        }
+
+        async fn controller(&self, _id: &str, _block: u64) -> Result<Controller, eyre::Error> {
+            Ok(controller())
+        }
+
+        async fn collateral(
+            &self,
+            _controller: &str,
+            _block: u64,
+        ) -> Result<Vec<String>, eyre::Error> {
+            Ok(vec![COLLECTION.to_string()])
+        }
+
+        async fn vaults_exceeding_debt_per_collateral(
+            &self,
+            _controller: &str,
+            _collateral: &str,
+            _debt_per_collateral: U256,
+            _latest_auction_before: u64,
+            _block: u64,
+        ) -> Result<Vec<Vault>, eyre::Error> {
+            Ok(vec![])
+        }
+    }
+
+    impl AuctionSource for FakeAuctions {
+        async fn ongoing_auctions(
+            &self,
+            _controller: &str,
+            _block: u64,
+        ) -> Result<Vec<SubgraphAuction>, eyre::Error> {
+            Ok(vec![auction(60, Address::zero())])
+        }
+    }
 
     #[test]
     fn current_price_computes_correctly() {
         use ongoing_auctions_by_controller::*;
-        let start_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            .checked_sub(4060)
-            .unwrap();
+        let start_time = 1674959723;
 
         let auction = SubgraphAuction {
             id: "84921541788424467252204917851547836642895224820573188317255928948100032289381"
@@ -141,15 +941,62 @@ mod tests {
                 decimals: 18,
             },
             start: OngoingAuctionsByControllerAuctionsStart {
-                timestamp: start_time as i64,
+                timestamp: start_time,
             },
         };
 
-        // computed from solidity = 270459742027958058
-        // 25 difference which is sub second. For scale, a second later the price is 3T lower
+        // computed from solidity
+        assert_eq!(
+            U256::from_dec_str("270459742027958058").unwrap(),
+            current_price(&auction, start_time as u64 + 4060).unwrap()
+        );
+        assert!(current_price(&auction, start_time as u64 - 1).is_err());
+    }
+
+    #[test]
+    fn opportunity_profit_requires_covering_gas() {
+        let opportunity = Opportunity {
+            cost: U256::from(900),
+            proceeds: U256::from(1000),
+            gas_cost: U256::from(60),
+        };
+        assert_eq!(Some(U256::from(40)), opportunity.profit());
+        assert_eq!(
+            None,
+            Opportunity {
+                gas_cost: U256::from(101),
+                ..opportunity
+            }
+            .profit()
+        );
+    }
+
+    #[test]
+    fn apply_discount_rounds_up() {
+        let ten_percent = U256::exp10(17);
+        assert_eq!(
+            U256::from(900),
+            apply_discount(U256::from(1000), ten_percent)
+        );
+        assert_eq!(
+            U256::from(901),
+            apply_discount(U256::from(1001), ten_percent)
+        );
+    }
+
+    #[test]
+    fn gas_cost_in_currency_uses_bid_exchange_rate() {
+        // a bid of 1.5 ETH worth 2400 USDC
+        let net_amount = NetAmount {
+            raw: "2400000000".into(),
+            decimal: 2400.0,
+            native: 1.5,
+        };
+        // 0.015 ETH of gas
+        let gas_cost = U256::from_dec_str("15000000000000000").unwrap();
         assert_eq!(
-            U256::from_dec_str("270459742027958030").unwrap(),
-            current_price(auction).unwrap()
+            U256::from(24_000_000),
+            gas_cost_in_currency(gas_cost, &net_amount, 6).unwrap()
         );
     }
 }
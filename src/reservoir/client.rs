@@ -5,31 +5,47 @@ use std::env;
 static API_KEY: Lazy<String> =
     Lazy::new(|| env::var("RESERVOIR_API_KEY").expect("RESERVOIR_API_KEY not set"));
 static BASE_URL: Lazy<String> =
-    Lazy::new(|| env::var("RESERVOIR_URL").expect("RESERVOIR_API_KEY not set"));
+    Lazy::new(|| env::var("RESERVOIR_URL").expect("RESERVOIR_URL not set"));
 
 pub struct ReservoirClient {
     client: reqwest::Client,
+    /// `RESERVOIR_URL` and `RESERVOIR_API_KEY` if not given
+    endpoint: Option<(String, String)>,
 }
 
 impl Default for ReservoirClient {
     fn default() -> Self {
         Self {
             client: reqwest::Client::new(),
+            endpoint: None,
         }
     }
 }
 
 impl ReservoirClient {
+    /// Points the client at a local server in tests
+    #[cfg(test)]
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: Some((base_url.into(), api_key.into())),
+        }
+    }
+
     pub async fn get<Q: Serialize, D: DeserializeOwned>(
         &self,
         url: &str,
         query: Q,
     ) -> Result<D, eyre::Error> {
+        let (base_url, api_key) = match &self.endpoint {
+            Some((base_url, api_key)) => (base_url.as_str(), api_key.as_str()),
+            None => (BASE_URL.as_str(), API_KEY.as_str()),
+        };
         let res = self
             .client
-            .get(format!("{}{}", BASE_URL.to_string(), url))
+            .get(format!("{}{}", base_url, url))
             .query(&query)
-            .header("api_key", API_KEY.to_string())
+            .header("api_key", api_key)
             .send()
             .await?
             .json::<D>()
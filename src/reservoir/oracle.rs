@@ -1,12 +1,24 @@
 use crate::papr_controller;
+use clap::ValueEnum;
 use ethers::{
-    types::{Bytes, Signature, U256},
-    utils::{hex::FromHex, parse_units},
+    abi::{decode, encode, ParamType, Token},
+    types::{Address, Bytes, Signature, H256, U256},
+    utils::{format_units, hash_message, hex::FromHex, keccak256},
 };
 use serde::Deserialize;
 use strum_macros::Display;
 
-#[derive(Display)]
+/// How long the controller accepts a signed oracle message for
+pub const ORACLE_MESSAGE_VALID_FOR_SECONDS: u64 = 20 * 60;
+/// Leave time for a transaction to be mined before its oracle message expires
+const ORACLE_MESSAGE_EXPIRY_MARGIN_SECONDS: u64 = 2 * 60;
+/// Type the oracle signs messages as, hashed like an EIP-712 struct
+const MESSAGE_TYPE: &str = "Message(bytes32 id,bytes payload,uint256 timestamp)";
+/// Relative difference allowed between the response's float price and the
+/// signed one, the float is rounded by Reservoir
+const PRICE_TOLERANCE: f64 = 1e-6;
+
+#[derive(Display, Clone, ValueEnum)]
 #[strum(serialize_all = "camelCase")]
 pub enum PriceKind {
     Upper,
@@ -38,29 +50,130 @@ pub struct OracleMessage {
     pub signature: Bytes,
 }
 
+/// The price a message's payload signs, the one the controller uses
+#[derive(Debug, PartialEq)]
+pub struct SignedPrice {
+    pub currency: Address,
+    /// in the currency's atomic units
+    pub price: U256,
+}
+
 impl OracleResponse {
-    pub fn price_in_atomic_units(&self, decimals: u32) -> Result<U256, eyre::Error> {
-        let one: U256 = parse_units(1, decimals)?.into();
-        // scalar to prevent loss of precison when converting to atomic
-        let scalar = 1e6;
-        let u256_scalar = U256::from_dec_str(&scalar.to_string())?;
-        let scaled_price = (self.price * scalar).floor();
-        let u256_scaled_price = U256::from_dec_str(&scaled_price.to_string())?;
-        let result = one
-            .checked_mul(u256_scaled_price)
-            .ok_or(eyre::eyre!("price_atomic overflow"))?
-            .checked_div(u256_scalar)
-            .ok_or(eyre::eyre!("price_atomic division error"))?;
-        Ok(result)
+    /// Decodes the signed price, rejecting a message in another currency than
+    /// `underlying`, which the controller reverts on with
+    /// `WrongCurrencyFromOracleMessage`, or whose price disagrees with `price`.
+    pub fn signed_price(
+        &self,
+        underlying: Address,
+        decimals: u32,
+    ) -> Result<SignedPrice, eyre::Error> {
+        let signed = self.message.decode_payload()?;
+        if signed.currency != underlying {
+            return Err(eyre::eyre!(
+                "oracle message priced in {:?}, controller underlying is {:?}",
+                signed.currency,
+                underlying
+            ));
+        }
+        let signed_price: f64 = format_units(signed.price, decimals)?.parse()?;
+        if (signed_price - self.price).abs() > signed_price.abs() * PRICE_TOLERANCE {
+            return Err(eyre::eyre!(
+                "oracle price {} does not match signed price {}",
+                self.price,
+                signed_price
+            ));
+        }
+        Ok(signed)
+    }
+
+    /// The signed price in the currency's atomic units, exactly as the
+    /// controller reads it. `price` is rounded and only fit for display.
+    pub fn price_in_atomic_units(&self) -> Result<U256, eyre::Error> {
+        Ok(self.message.decode_payload()?.price)
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum MessageValidity {
+    Valid,
+    /// signed after the latest block, the controller rejects messages from the future
+    Ahead {
+        seconds: u64,
+    },
+    /// expired or about to before a transaction using it could be mined
+    Expiring,
+}
+
 impl OracleMessage {
+    pub fn validity(&self, block_timestamp: u64) -> MessageValidity {
+        if self.timestamp > block_timestamp {
+            return MessageValidity::Ahead {
+                seconds: self.timestamp - block_timestamp,
+            };
+        }
+        if self.timestamp + ORACLE_MESSAGE_VALID_FOR_SECONDS
+            < block_timestamp + ORACLE_MESSAGE_EXPIRY_MARGIN_SECONDS
+        {
+            return MessageValidity::Expiring;
+        }
+        MessageValidity::Valid
+    }
+
+    /// The digest the controller recovers the signer from: the EIP-191 hash of
+    /// the message's struct hash.
+    pub fn signing_hash(&self) -> Result<H256, eyre::Error> {
+        let struct_hash = keccak256(encode(&[
+            Token::FixedBytes(keccak256(MESSAGE_TYPE).to_vec()),
+            Token::FixedBytes(self.id_bytes()?.to_vec()),
+            Token::FixedBytes(keccak256(&self.payload).to_vec()),
+            Token::Uint(self.timestamp.into()),
+        ]));
+        Ok(hash_message(struct_hash))
+    }
+
+    pub fn signer(&self) -> Result<Address, eyre::Error> {
+        let signature = self.signature.to_string().parse::<Signature>()?;
+        Ok(signature.recover(self.signing_hash()?)?)
+    }
+
+    /// Rejects a message the controller would revert on with `IncorrectOracleSigner`
+    pub fn ensure_signed_by(&self, oracle_signer: Address) -> Result<(), eyre::Error> {
+        let signer = self.signer()?;
+        if signer != oracle_signer {
+            return Err(eyre::eyre!(
+                "oracle message signed by {:?}, controller expects {:?}",
+                signer,
+                oracle_signer
+            ));
+        }
+        Ok(())
+    }
+
+    /// The payload as `abi.encode(address currency, uint256 price)`
+    pub fn decode_payload(&self) -> Result<SignedPrice, eyre::Error> {
+        let tokens = decode(&[ParamType::Address, ParamType::Uint(256)], &self.payload)?;
+        match tokens.as_slice() {
+            [Token::Address(currency), Token::Uint(price)] => Ok(SignedPrice {
+                currency: *currency,
+                price: *price,
+            }),
+            _ => Err(eyre::eyre!("unexpected oracle payload {}", self.payload)),
+        }
+    }
+
+    fn id_bytes(&self) -> Result<[u8; 32], eyre::Error> {
+        let hex = self
+            .id
+            .strip_prefix("0x")
+            .ok_or(eyre::eyre!("oracle message id {} is not hex", self.id))?;
+        Ok(<[u8; 32]>::from_hex(hex)?)
+    }
+
     pub fn as_contract_oracle_info(&self) -> Result<papr_controller::OracleInfo, eyre::Error> {
         let signature_struct = self.signature.to_string().parse::<Signature>()?;
         let info = papr_controller::OracleInfo {
             message: papr_controller::Message {
-                id: <[u8; 32]>::from_hex(&self.id[2..])?,
+                id: self.id_bytes()?,
                 payload: self.payload.clone(),
                 timestamp: self.timestamp.into(),
                 signature: self.signature.clone(),
@@ -75,8 +188,21 @@ impl OracleMessage {
     }
 }
 
-impl crate::reservoir::client::ReservoirClient {
-    pub async fn max_collection_bid(
+/// Signed collection prices the controller accepts, so liquidation can run
+/// against a fake in tests.
+pub trait PriceOracle {
+    async fn max_collection_bid(
+        &self,
+        collection: &str,
+        price_kind: PriceKind,
+        quote_currency: &str,
+        twap_seconds: Option<u32>,
+    ) -> Result<OracleResponse, eyre::Error>;
+}
+
+impl PriceOracle for crate::reservoir::client::ReservoirClient {
+    #[allow(clippy::needless_borrow, clippy::needless_question_mark)]
+    async fn max_collection_bid(
         &self,
         collection: &str,
         price_kind: PriceKind,
@@ -107,53 +233,142 @@ impl crate::reservoir::client::ReservoirClient {
 
 #[cfg(test)]
 mod tests {
+    use crate::mock_server::{fixture, MockServer};
+    use crate::reservoir::client::ReservoirClient;
+    use crate::reservoir::oracle::MessageValidity;
     use crate::reservoir::oracle::OracleMessage;
     use crate::reservoir::oracle::OracleResponse;
-    use ethers::types::{Bytes, U256};
+    use crate::reservoir::oracle::SignedPrice;
+    use crate::reservoir::oracle::{PriceKind, PriceOracle};
+    use ethers::{
+        abi::{encode, Token},
+        types::{Address, Bytes, U256},
+    };
     use std::str::FromStr;
 
+    #[tokio::test]
+    async fn max_collection_bid_queries_top_bid_endpoint() {
+        let server = MockServer::start(|request| {
+            (request.path == "/oracle/collections/top-bid/v2")
+                .then(|| fixture(include_str!("../fixtures/reservoir_top_bid.json")))
+        });
+        let response = ReservoirClient::new(server.url(), "test-key")
+            .max_collection_bid(
+                "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d",
+                PriceKind::Twap,
+                "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                Some(604800),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.price, 64.7);
+        assert_eq!(response.message.timestamp, 1674959723);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].header("api_key"), Some("test-key"));
+        assert_eq!(
+            requests[0].query_param("collection"),
+            Some("0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d")
+        );
+        assert_eq!(requests[0].query_param("kind"), Some("twap"));
+        assert_eq!(
+            requests[0].query_param("currency"),
+            Some("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")
+        );
+        assert_eq!(requests[0].query_param("twapSeconds"), Some("604800"));
+    }
+
     #[test]
-    fn price_in_atomic_units_computes_correctly() {
+    fn price_in_atomic_units_reads_signed_payload() {
         let response = OracleResponse {
-            price: 1.0,
-            message: OracleMessage {
-                id: "0x1213".to_string(),
-                payload: Bytes::from_str("0x1213").unwrap(),
-                signature: Bytes::from_str("0x1213").unwrap(),
-                timestamp: 1,
-            },
+            price: 64.695,
+            message: reservoir_message(),
         };
         assert_eq!(
-            response.price_in_atomic_units(6).unwrap(),
-            U256::from_dec_str(&1e6.to_string()).unwrap()
+            response.price_in_atomic_units().unwrap(),
+            U256::from_dec_str("64695000000000000000").unwrap()
         );
     }
 
     #[test]
-    fn price_in_atomic_units_keeps_six_digits_precision() {
+    fn price_in_atomic_units_keeps_precision_floats_lose() {
+        // beyond the 53 bits of an f64 mantissa
+        let price = U256::from_dec_str("123456789012345678901234567").unwrap();
         let response = OracleResponse {
-            price: 1.1234567,
+            price: 123456789.01234567,
             message: OracleMessage {
-                id: "0x1213".to_string(),
-                payload: Bytes::from_str("0x1213").unwrap(),
-                signature: Bytes::from_str("0x1213").unwrap(),
-                timestamp: 1,
+                payload: encode(&[Token::Address(Address::zero()), Token::Uint(price)]).into(),
+                ..message_at(1)
             },
         };
+        assert_eq!(response.price_in_atomic_units().unwrap(), price);
+        assert!(response.signed_price(Address::zero(), 18).is_ok());
+    }
+
+    #[test]
+    fn signed_price_decodes_payload() {
+        let response = OracleResponse {
+            price: 64.695,
+            message: reservoir_message(),
+        };
         assert_eq!(
-            response.price_in_atomic_units(6).unwrap(),
-            U256::from_dec_str(&1.123456e6.to_string()).unwrap()
+            response.signed_price(Address::zero(), 18).unwrap(),
+            SignedPrice {
+                currency: Address::zero(),
+                price: U256::from_dec_str("64695000000000000000").unwrap(),
+            }
         );
     }
 
     #[test]
-    fn as_contract_oracle_info_converts_values_correctly() {
-        let message = OracleMessage {
-            id: "0xc8c8fbbc02b65d74cc2266c31a5d773f5b73983830d7757ba80a14175f0fb189".to_string(),
-            payload: Bytes::from_str("0x000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000381d2cfbb2fe58000").unwrap(),
-            signature: Bytes::from_str("0xcb14779852fb3cebb98cb5ee807051d162396108b65822a2c52147d739a100fb00185d38f2a4542e2e90c7f6493fad340333b5e1f9c2f20c2ed1956a56340e021c").unwrap(),
-            timestamp: 1674959723,
+    fn signed_price_rejects_other_currency_and_price() {
+        let response = OracleResponse {
+            price: 64.695,
+            message: reservoir_message(),
+        };
+        let weth: Address = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            response.signed_price(weth, 18).unwrap_err().to_string(),
+            format!(
+                "oracle message priced in {:?}, controller underlying is {:?}",
+                Address::zero(),
+                weth
+            )
+        );
+
+        let response = OracleResponse {
+            price: 70.0,
+            ..response
         };
+        assert!(response.signed_price(Address::zero(), 18).is_err());
+        assert!(message_at(1).decode_payload().is_err());
+    }
+
+    #[test]
+    fn signer_recovers_reservoir_oracle_signer() {
+        let message = reservoir_message();
+        let reservoir: Address = "0xaeb1d03929bf87f69888f381e73fbf75753d75af"
+            .parse()
+            .unwrap();
+        assert_eq!(message.signer().unwrap(), reservoir);
+        assert!(message.ensure_signed_by(reservoir).is_ok());
+        assert!(message.ensure_signed_by(Address::zero()).is_err());
+
+        // a tampered message no longer recovers to the oracle
+        let tampered = OracleMessage {
+            timestamp: message.timestamp + 1,
+            ..reservoir_message()
+        };
+        assert!(tampered.ensure_signed_by(reservoir).is_err());
+    }
+
+    #[test]
+    fn as_contract_oracle_info_converts_values_correctly() {
+        let message = reservoir_message();
         let info = message.as_contract_oracle_info().unwrap();
         assert_eq!(
             info.message.timestamp.to_string(),
@@ -185,4 +400,44 @@ mod tests {
         );
         assert_eq!(info.sig.v, 28);
     }
+
+    #[test]
+    fn validity_waits_for_block_to_catch_up() {
+        let message = message_at(1674959723);
+        assert_eq!(
+            message.validity(1674959713),
+            MessageValidity::Ahead { seconds: 10 }
+        );
+        assert_eq!(message.validity(1674959723), MessageValidity::Valid);
+    }
+
+    #[test]
+    fn validity_expires_before_contract_window_closes() {
+        let message = message_at(1674959723);
+        // 20 minutes valid less the 2 minute margin
+        assert_eq!(message.validity(1674959723 + 1080), MessageValidity::Valid);
+        assert_eq!(
+            message.validity(1674959723 + 1081),
+            MessageValidity::Expiring
+        );
+    }
+
+    /// A message signed by Reservoir's oracle
+    fn reservoir_message() -> OracleMessage {
+        OracleMessage {
+            id: "0xc8c8fbbc02b65d74cc2266c31a5d773f5b73983830d7757ba80a14175f0fb189".to_string(),
+            payload: Bytes::from_str("0x000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000381d2cfbb2fe58000").unwrap(),
+            signature: Bytes::from_str("0xcb14779852fb3cebb98cb5ee807051d162396108b65822a2c52147d739a100fb00185d38f2a4542e2e90c7f6493fad340333b5e1f9c2f20c2ed1956a56340e021c").unwrap(),
+            timestamp: 1674959723,
+        }
+    }
+
+    fn message_at(timestamp: u64) -> OracleMessage {
+        OracleMessage {
+            id: "0x1213".to_string(),
+            payload: Bytes::from_str("0x1213").unwrap(),
+            signature: Bytes::from_str("0x1213").unwrap(),
+            timestamp,
+        }
+    }
 }
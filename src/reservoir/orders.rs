@@ -5,6 +5,7 @@ use strum_macros::Display;
 #[strum(serialize_all = "camelCase")]
 enum OrderQueryParam {
     Collection,
+    Ids,
     Limit,
     SortBy,
 }
@@ -23,6 +24,7 @@ pub struct Order {
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Price {
     pub amount: Amount,
     pub net_amount: NetAmount,
@@ -32,20 +34,19 @@ pub struct Price {
 #[derive(Deserialize)]
 pub struct Amount {
     pub usd: f64,
-    native: f64,
 }
 
 #[derive(Deserialize)]
 pub struct NetAmount {
-    raw: String,
+    pub raw: String,
     pub decimal: f64,
-    native: f64,
+    pub native: f64,
 }
 
 #[derive(Deserialize)]
 pub struct Currency {
-    contract: String,
-    decimals: u8,
+    pub contract: String,
+    pub decimals: u8,
 }
 
 #[derive(Deserialize)]
@@ -54,6 +55,7 @@ pub struct Criteria {
 }
 
 impl crate::reservoir::client::ReservoirClient {
+    #[allow(clippy::needless_borrow, clippy::needless_question_mark)]
     pub async fn bids(
         &self,
         collection: &str,
@@ -72,4 +74,67 @@ impl crate::reservoir::client::ReservoirClient {
         }
         Ok(self.get::<_, BidsResponse>(&url, query).await?)
     }
+
+    pub async fn bids_by_ids(&self, ids: &[String]) -> Result<BidsResponse, eyre::Error> {
+        let url = "/orders/bids/v5";
+        let query: Vec<(String, String)> = ids
+            .iter()
+            .map(|id| (OrderQueryParam::Ids.to_string(), id.clone()))
+            .collect();
+        self.get::<_, BidsResponse>(url, query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mock_server::{fixture, MockServer},
+        reservoir::client::ReservoirClient,
+    };
+
+    #[tokio::test]
+    async fn bids_parses_orders_sorted_by_price() {
+        let server = MockServer::start(|request| {
+            (request.path == "/orders/bids/v5")
+                .then(|| fixture(include_str!("../fixtures/reservoir_bids.json")))
+        });
+        let response = ReservoirClient::new(server.url(), "test-key")
+            .bids("0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d", Some(2))
+            .await
+            .unwrap();
+
+        assert_eq!(response.orders.len(), 2);
+        let best = &response.orders[0];
+        assert_eq!(best.kind, "seaport-v1.4");
+        assert_eq!(best.criteria.kind, "collection");
+        assert_eq!(best.price.amount.usd, 103350.5);
+        assert_eq!(best.price.net_amount.raw, "63375000000000000000");
+        assert_eq!(
+            best.price.currency.contract,
+            "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"
+        );
+        let request = &server.requests()[0];
+        assert_eq!(request.query_param("sortBy"), Some("price"));
+        assert_eq!(request.query_param("limit"), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn bids_by_ids_sends_each_id() {
+        let server =
+            MockServer::start(|_| Some(fixture(include_str!("../fixtures/reservoir_bids.json"))));
+        let ids = vec!["0x01".to_string(), "0x02".to_string()];
+        ReservoirClient::new(server.url(), "test-key")
+            .bids_by_ids(&ids)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        let sent: Vec<&str> = request
+            .query
+            .iter()
+            .filter(|(key, _)| key == "ids")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        assert_eq!(sent, vec!["0x01", "0x02"]);
+    }
 }
@@ -1,4 +1,7 @@
-use ethers::types::U256;
+use crate::reservoir::{client::ReservoirClient, orders::BidsResponse};
+use ethers::types::{
+    transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, U256,
+};
 use serde::Deserialize;
 use strum_macros::Display;
 
@@ -12,17 +15,23 @@ enum QueryParams {
 #[derive(Deserialize)]
 pub struct Response {
     pub steps: Vec<Step>,
+    /// the bids the sale would fill
+    #[serde(default)]
+    pub path: Vec<Path>,
 }
 
 #[derive(Deserialize)]
 pub struct Step {
+    pub id: String,
+    /// `transaction` or `signature`
+    pub kind: String,
     pub items: Vec<Item>,
 }
 
 #[derive(Deserialize)]
 pub struct Item {
-    status: String,
-    data: ItemData,
+    pub status: String,
+    pub data: Option<ItemData>,
 }
 
 #[derive(Deserialize)]
@@ -30,9 +39,70 @@ pub struct ItemData {
     from: String,
     to: String,
     data: String,
+    value: Option<String>,
 }
 
-impl crate::reservoir::client::ReservoirClient {
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Path {
+    pub order_id: String,
+}
+
+impl Step {
+    pub fn is_transaction(&self) -> bool {
+        self.kind == "transaction"
+    }
+}
+
+impl Item {
+    pub fn is_incomplete(&self) -> bool {
+        self.status == "incomplete"
+    }
+}
+
+impl ItemData {
+    pub fn as_transaction(&self) -> Result<TypedTransaction, eyre::Error> {
+        let mut tx = TransactionRequest::new()
+            .from(self.from.parse::<Address>()?)
+            .to(self.to.parse::<Address>()?)
+            .data(self.data.parse::<Bytes>()?);
+        if let Some(value) = &self.value {
+            tx = tx.value(U256::from_dec_str(value)?);
+        }
+        Ok(tx.into())
+    }
+}
+
+/// Where purchased NFTs are sold, so purchasing can run against a fake in tests.
+pub trait Marketplace {
+    /// The steps for `seller` to sell the token into its best bid, with the bids
+    /// the sale would fill
+    async fn sell_token(
+        &self,
+        collection: &str,
+        token_id: U256,
+        seller: String,
+    ) -> Result<Response, eyre::Error>;
+
+    async fn bids_by_ids(&self, ids: &[String]) -> Result<BidsResponse, eyre::Error>;
+}
+
+impl Marketplace for ReservoirClient {
+    async fn sell_token(
+        &self,
+        collection: &str,
+        token_id: U256,
+        seller: String,
+    ) -> Result<Response, eyre::Error> {
+        ReservoirClient::sell_token(self, collection, token_id, seller).await
+    }
+
+    async fn bids_by_ids(&self, ids: &[String]) -> Result<BidsResponse, eyre::Error> {
+        ReservoirClient::bids_by_ids(self, ids).await
+    }
+}
+
+impl ReservoirClient {
     pub async fn sell_token(
         &self,
         collection: &str,
@@ -43,10 +113,104 @@ impl crate::reservoir::client::ReservoirClient {
         let query: Vec<(String, String)> = vec![
             (
                 QueryParams::Token.to_string(),
-                format!("{}:{}", collection, token_id.to_string()),
+                format!("{}:{}", collection, token_id),
             ),
             (QueryParams::Taker.to_string(), seller),
         ];
-        Ok(self.get::<_, Response>(&url, query).await?)
+        self.get::<_, Response>(url, query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock_server::{fixture, MockServer};
+    use crate::reservoir::{client::ReservoirClient, sell::Response};
+    use ethers::types::{Address, U256};
+
+    #[tokio::test]
+    async fn sell_token_returns_steps_and_path() {
+        let server = MockServer::start(|request| {
+            (request.path == "/execute/sell/v6")
+                .then(|| fixture(include_str!("../fixtures/reservoir_sell.json")))
+        });
+        let response = ReservoirClient::new(server.url(), "test-key")
+            .sell_token(
+                "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d",
+                U256::from(7),
+                "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = response.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["nft-approval", "sale"]);
+        assert!(response
+            .steps
+            .iter()
+            .all(|step| step.is_transaction() && step.items[0].is_incomplete()));
+        assert_eq!(
+            response.path[0].order_id,
+            "0x8f5bb3b3a3c3e8b2d0e0b0a4d2c1a3c9e7c1b0d3a6f3e8c2b1a0d9e8f7c6b5a4"
+        );
+        let request = &server.requests()[0];
+        assert_eq!(
+            request.query_param("token"),
+            Some("0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d:7")
+        );
+        assert_eq!(
+            request.query_param("taker"),
+            Some("0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266")
+        );
+    }
+
+    #[test]
+    fn response_parses_steps_and_path() {
+        let response: Response = serde_json::from_value(serde_json::json!({
+            "steps": [{
+                "id": "sale",
+                "action": "Accept offer",
+                "description": "To sell this item you must confirm the transaction and pay the gas fee",
+                "kind": "transaction",
+                "items": [{
+                    "status": "incomplete",
+                    "data": {
+                        "from": "0xe89cb2053a04daf86abaa1f4bc6d50744e57d39e",
+                        "to": "0x00000000000000adc04c56bf30ac9d3c0aaf14dc",
+                        "data": "0xdeadbeef",
+                        "value": "0"
+                    }
+                }]
+            }],
+            "path": [{
+                "orderId": "0x7f2e3c1a2e5b3dc1e1a9cb5fa5cdf83b5e0c0d2b4f4e2b7d66c4f0bde8a9f010",
+                "contract": "0x79ab709dadc05cd2c0f7322bc7e3d70d2550942c",
+                "tokenId": "10",
+                "quantity": 1,
+                "source": "opensea.io",
+                "currency": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+                "quote": 0.95,
+                "rawQuote": "950000000000000000"
+            }]
+        }))
+        .unwrap();
+
+        let step = &response.steps[0];
+        assert!(step.is_transaction());
+        assert!(step.items[0].is_incomplete());
+        let tx = step.items[0]
+            .data
+            .as_ref()
+            .unwrap()
+            .as_transaction()
+            .unwrap();
+        assert_eq!(
+            tx.to_addr(),
+            Some(
+                &"0x00000000000000adc04c56bf30ac9d3c0aaf14dc"
+                    .parse::<Address>()
+                    .unwrap()
+            )
+        );
+        assert_eq!(tx.value(), Some(&U256::zero()));
     }
 }
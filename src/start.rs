@@ -1,153 +1,756 @@
 use crate::{
-    papr_controller::{Collateral, PaprController},
-    papr_subgraph::client::GraphQLClient,
-    papr_subgraph::queries::{
-        all_controllers::AllControllersPaprControllers as Controller,
-        vaults_exceeding_debt_per_collateral::VaultsExceedingDebtPerCollateralVaults as Vault,
+    config::{Config, ControllerConfig},
+    journal::{AuctionAttempt, Journal, Outcome},
+    papr_controller::{
+        Collateral, ControllerExecutor, LiquidationReceipt, OracleInfo, PaprControllerABIErrors,
+        SimulationError, StartedAuction, VaultInfo,
     },
-    reservoir::{client::ReservoirClient, oracle::OracleResponse, oracle::PriceKind},
+    reservoir::oracle::{MessageValidity, OracleResponse, PriceKind, PriceOracle},
+    vault_source::{Controller, Vault, VaultSource},
 };
-use ethers::types::{Address, U256};
-use once_cell::sync::Lazy;
+use ethers::types::{Address, TransactionReceipt, U256};
+use futures::{stream, StreamExt, TryStreamExt};
 use std::{
-    collections::HashSet,
-    env,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use tokio::sync::Semaphore;
 
-const SEVEN_DAYS_SECONDS: u32 = 604800;
-const TWO_DAYS_SECONDS: u64 = 172800;
-static DISABLE_EXECUTE_START_ACTION: Lazy<bool> =
-    Lazy::new(|| env::var("DISABLE_EXECUTE_START_ACTION").unwrap_or("false".to_string()) == "true");
-
-
-pub static WHITELIST: Lazy<HashSet<&'static str>> = Lazy::new(|| {
-    let mut m = HashSet::new();
-    // goerli paprHero
-    m.insert("0xd0a830278773282bbf635fd8e47b2447f1e9fe86");
-    m.insert("0x092018ff54df5bfa53e1c6e75ad0e2d8991a8b1e");
-    m.insert("0x937968d77f8e312574d659ccd9a527ec063ff601");
-    // paprMeme
-    m.insert("0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0");
-    m
-});
-
-pub async fn start_liquidations_for_whitelisted_controllers(
-    reservoir: &ReservoirClient,
-    graphql: &GraphQLClient,
-) -> Result<(), eyre::Error> {
-    let controllers = graphql.all_papr_controllers().await?;
+const SIMULATION_ATTEMPTS: u32 = 3;
+const SIMULATION_RETRY_DELAY: Duration = Duration::from_secs(5);
+const ORACLE_REFETCH_ATTEMPTS: u32 = 3;
+/// Polls for receipts of sent transactions, a few minutes of blocks in all
+const RECEIPT_POLLS: u32 = 60;
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(4);
+/// Polls a transaction stays unmined before it is replaced with higher fees
+const REPLACE_AFTER_POLLS: u32 = 15;
+/// Replacements of a transaction at most, so its fees cannot grow unbounded
+const FEE_BUMPS: usize = 5;
+
+/// Per controller state shared by each collateral's liquidation pass
+struct ControllerContext<'a, E> {
+    controller: &'a Controller,
+    config: &'a ControllerConfig,
+    provider: &'a E,
+    target: U256,
+    auction_min_spacing: U256,
+    oracle_signer: Address,
+    journal: &'a Journal,
+    /// block every vault source read of this pass is made at
+    snapshot_block: u64,
+    /// transactions sent this pass or still unmined from earlier ones, settled
+    /// in the journal once mined
+    pending: Mutex<Vec<PendingLiquidation>>,
+}
+
+impl<E> ControllerContext<'_, E> {
+    /// Whether a start auction of the vault's collateral is waiting to be mined
+    fn has_pending(&self, vault: &str, collateral: &str) -> Result<bool, eyre::Error> {
+        Ok(self
+            .pending
+            .lock()
+            .map_err(|_| eyre::eyre!("pending lock poisoned"))?
+            .iter()
+            .any(|liquidation| {
+                let latest = liquidation.latest();
+                latest.vault == vault && latest.collateral == collateral
+            }))
+    }
+}
+
+/// A nonce a start auction was sent at, waiting to be mined
+struct PendingLiquidation {
+    /// the journaled `Sent` record of each transaction at the nonce, the
+    /// highest fees last
+    sent: Vec<AuctionAttempt>,
+    nonce: U256,
+    /// polls since the last transaction was sent
+    polls: u32,
+}
+
+impl PendingLiquidation {
+    fn new(attempt: AuctionAttempt, nonce: U256) -> Self {
+        Self {
+            sent: vec![attempt],
+            nonce,
+            polls: 0,
+        }
+    }
 
-    for controller in controllers {
-        if WHITELIST.contains(&*controller.id) {
-            println!("starting for {}", controller.id);
-            println!("quote currency {}", controller.underlying.id);
-            start_liqudations_for_controller(controller, reservoir, graphql).await?;
+    /// The `Sent` records earlier passes did not settle, by nonce
+    fn resumed(unsettled: Vec<AuctionAttempt>) -> Result<Vec<Self>, eyre::Error> {
+        let mut pending: Vec<Self> = vec![];
+        for attempt in unsettled {
+            let (Some(_), Some(nonce)) = (attempt.tx_hash, attempt.nonce) else {
+                return Err(eyre::eyre!("sent attempt without tx hash and nonce"));
+            };
+            match pending
+                .iter_mut()
+                .find(|liquidation| liquidation.nonce == nonce)
+            {
+                Some(liquidation) => liquidation.sent.push(attempt),
+                None => pending.push(Self::new(attempt, nonce)),
+            }
         }
+        Ok(pending)
     }
+
+    fn latest(&self) -> &AuctionAttempt {
+        self.sent.last().expect("a pending nonce has a transaction")
+    }
+}
+
+/// How one liquidation pass runs
+#[derive(Clone)]
+struct Pass {
+    /// block every vault source read of the pass is made at
+    snapshot_block: u64,
+    /// permits to scan a collateral, shared by every controller of the pass
+    scans: Arc<Semaphore>,
+    /// find liquidatable vaults without sending transactions
+    dry_run: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum VaultStatus {
+    NotLiquidatable,
+    /// the vault had an auction too recently, the next one can start at `next_start`
+    AuctionSpacing {
+        next_start: U256,
+    },
+    Liquidatable,
+}
+
+/// Starts liquidation auctions for the vaults of every controller configured
+/// for liquidating. `executor` builds the controller at an address.
+pub async fn start_liquidations_for_whitelisted_controllers<E: ControllerExecutor>(
+    oracle: &impl PriceOracle,
+    vaults: &impl VaultSource,
+    executor: impl Fn(&str) -> Result<E, eyre::Error>,
+    config: &Config,
+    journal: &Journal,
+    dry_run: bool,
+) -> Result<(), eyre::Error> {
+    let snapshot_block = vaults
+        .snapshot_block(config.max_subgraph_lag_blocks)
+        .await?;
+    println!("reading vaults at block {}", snapshot_block);
+
+    let executor = &executor;
+    let scans = &Arc::new(Semaphore::new(config.scan_concurrency));
+    // controllers only wait on the scans of their collateral
+    stream::iter(config.liquidation_controllers().map(Ok))
+        .try_for_each_concurrent(None, |controller_config| async move {
+            println!(
+                "starting for {} {}",
+                controller_config.name.as_deref().unwrap_or(""),
+                controller_config.address
+            );
+            // config addresses are validated so this cannot fail
+            let provider = executor(&controller_config.address)?;
+            // one bad controller should not stop us from liquidating the others
+            if let Err(err) = start_liqudations_for_controller(
+                controller_config,
+                journal,
+                oracle,
+                vaults,
+                &provider,
+                Pass {
+                    snapshot_block,
+                    scans: Arc::clone(scans),
+                    dry_run,
+                },
+            )
+            .await
+            {
+                println!(
+                    "error liquidating controller {}: {}",
+                    controller_config.address, err
+                );
+            }
+            Ok::<_, eyre::Error>(())
+        })
+        .await?;
     Ok(())
 }
 
 async fn start_liqudations_for_controller(
-    controller: Controller,
-    reservoir: &ReservoirClient,
-    graphql: &GraphQLClient,
+    config: &ControllerConfig,
+    journal: &Journal,
+    oracle: &impl PriceOracle,
+    vaults: &impl VaultSource,
+    provider: &impl ControllerExecutor,
+    pass: Pass,
 ) -> Result<(), eyre::Error> {
-    let controller_provider = PaprController::new(&controller.id)?;
-    let target = controller_provider.new_target().await?;
-    let max_ltv = controller.max_ltv_as_u256()?;
+    let Pass {
+        snapshot_block,
+        scans,
+        dry_run,
+    } = pass;
+    let controller = vaults.controller(&config.address, snapshot_block).await?;
+    println!("quote currency {}", controller.underlying);
+    let target = provider.new_target().await?;
+    let auction_min_spacing = provider.liquidation_auction_min_spacing().await?;
+    let oracle_signer = provider.oracle_signer().await?;
     println!("target {}", target);
-    println!("max_ltv {}", max_ltv);
-    for collateral in controller.allowed_collateral {
-        println!("fetching price for collateral {}", collateral.token.id);
-        let oracle_response_result = reservoir
-            .max_collection_bid(
-                &collateral.token.id,
-                PriceKind::Twap,
-                &controller.underlying.id,
-                Some(SEVEN_DAYS_SECONDS),
-            )
-            .await;
-        if let Some(err) = oracle_response_result.as_ref().err() {
-            // mainly to handle goerli issues
-            println!("oracle err: {}", err);
-            continue;
+    println!("max_ltv {}", controller.max_ltv);
+    let context = ControllerContext {
+        controller: &controller,
+        config,
+        provider,
+        target,
+        auction_min_spacing,
+        oracle_signer,
+        journal,
+        snapshot_block,
+        pending: Mutex::new(vec![]),
+    };
+    if !dry_run {
+        // checked first so the scan sees the auctions they started, but not
+        // waited on so a stuck transaction cannot hold up the scan
+        let unsettled = journal.unsettled(&controller.id)?;
+        if !unsettled.is_empty() {
+            let resumed = PendingLiquidation::resumed(unsettled)?;
+            match poll_pending_liquidations(&context, resumed).await {
+                Ok(unmined) => {
+                    if !unmined.is_empty() {
+                        println!(
+                            "{} start auctions sent earlier still unmined",
+                            unmined.len()
+                        );
+                    }
+                    *context
+                        .pending
+                        .lock()
+                        .map_err(|_| eyre::eyre!("pending lock poisoned"))? = unmined;
+                }
+                // still `Sent` in the journal, the next pass checks them again
+                Err(err) => println!("error checking start auctions sent earlier: {}", err),
+            }
+        }
+    }
+    let collateral = vaults
+        .collateral(&controller.id, snapshot_block)
+        .await?
+        .into_iter()
+        .filter(|collateral| config.collateral_enabled(collateral));
+    let (context, scans) = (&context, &scans);
+    stream::iter(collateral)
+        .for_each_concurrent(None, |collateral| async move {
+            // never closed
+            let Ok(_scan) = scans.acquire().await else {
+                return;
+            };
+            if let Err(err) =
+                start_liquidations_for_collateral(context, &collateral, oracle, vaults, dry_run)
+                    .await
+            {
+                println!("error liquidating collateral {}: {}", collateral, err);
+            }
+        })
+        .await;
+    settle_pending_liquidations(context).await
+}
+
+async fn start_liquidations_for_collateral(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    collateral: &str,
+    oracle: &impl PriceOracle,
+    vaults: &impl VaultSource,
+    dry_run: bool,
+) -> Result<(), eyre::Error> {
+    let controller = context.controller;
+    println!("fetching price for collateral {}", collateral);
+    let oracle_response_result = fetch_oracle_response(context, collateral, oracle).await;
+    if let Some(err) = oracle_response_result.as_ref().err() {
+        // mainly to handle goerli issues
+        println!("oracle err: {}", err);
+        return Ok(());
+    }
+    let oracle_response = oracle_response_result?;
+    let price = oracle_response.price_in_atomic_units()?;
+    println!("price {} ({})", oracle_response.price, price);
+    let max = max_debt(price, controller.max_ltv, context.target)?;
+    println!("max debt {}", max);
+    let liquidatable_vaults = vaults
+        .vaults_exceeding_debt_per_collateral(
+            &controller.id,
+            collateral,
+            max,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)?
+                .as_secs()
+                .checked_sub(context.config.auction_spacing_seconds)
+                .ok_or(eyre::eyre!("timestamp error"))?,
+            context.snapshot_block,
+        )
+        .await?;
+    println!("found {} liquidatable vaults", liquidatable_vaults.len());
+    start_liquidations_for_vaults(
+        context,
+        liquidatable_vaults,
+        oracle_response,
+        oracle,
+        dry_run,
+    )
+    .await
+}
+
+async fn fetch_oracle_response(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    collateral: &str,
+    oracle: &impl PriceOracle,
+) -> Result<OracleResponse, eyre::Error> {
+    let response = oracle
+        .max_collection_bid(
+            collateral,
+            PriceKind::Twap,
+            &context.controller.underlying,
+            Some(context.config.twap_seconds),
+        )
+        .await?;
+    response.message.ensure_signed_by(context.oracle_signer)?;
+    response.signed_price(
+        context.controller.underlying.parse()?,
+        context.controller.underlying_decimals as u32,
+    )?;
+    Ok(response)
+}
+
+/// Waits for the chain to catch up with the oracle message's timestamp, replacing
+/// the message when it is too old for the controller to accept.
+async fn ensure_valid_oracle_message(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    collateral: &str,
+    oracle: &impl PriceOracle,
+    oracle_response: &mut OracleResponse,
+) -> Result<(), eyre::Error> {
+    let mut refetches = 0;
+    loop {
+        let block_timestamp = context.provider.latest_block_timestamp().await?;
+        match oracle_response.message.validity(block_timestamp) {
+            MessageValidity::Valid => return Ok(()),
+            MessageValidity::Ahead { seconds } => {
+                println!(
+                    "oracle message {} seconds ahead of latest block, waiting",
+                    seconds
+                );
+                tokio::time::sleep(Duration::from_secs(seconds)).await;
+            }
+            MessageValidity::Expiring => {
+                if refetches == ORACLE_REFETCH_ATTEMPTS {
+                    return Err(eyre::eyre!("could not get a valid oracle message"));
+                }
+                println!("oracle message too old, refetching");
+                *oracle_response = fetch_oracle_response(context, collateral, oracle).await?;
+                refetches += 1;
+            }
         }
-        let oracle_response = oracle_response_result?;
-        println!(
-            "price {}",
-            oracle_response
-                .price_in_atomic_units(controller.underlying.decimals as u32)
-                .unwrap()
-        );
-        let max = max_debt(
-            oracle_response.price_in_atomic_units(controller.underlying.decimals as u32)?,
-            max_ltv,
-            target,
-        )?;
-        println!("max debt {}", max);
-        let liquidatable_vaults = graphql
-            .collateral_vaults_exceeding_debt_per_collateral(
-                &controller.id,
-                &collateral.token.id,
-                max,
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)?
-                    .as_secs()
-                    .checked_sub(TWO_DAYS_SECONDS)
-                    .ok_or(eyre::eyre!("timestamp error"))?,
-            )
-            .await?;
-        println!("found {} liquidatable vaults", liquidatable_vaults.len());
-        start_liquidations_for_vaults(liquidatable_vaults, oracle_response, &controller_provider)
-            .await?;
-        // TODO should store auction IDs of started auctions so that we can remember we have a discount
     }
-    Ok(())
 }
 
 async fn start_liquidations_for_vaults(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
     vaults: Vec<Vault>,
-    oracle_response: OracleResponse,
-    controller_provider: &PaprController,
+    mut oracle_response: OracleResponse,
+    oracle: &impl PriceOracle,
+    dry_run: bool,
 ) -> Result<(), eyre::Error> {
-    // have to sleep otherwise timestamp will be > block.timestamp
-    std::thread::sleep(std::time::Duration::from_secs(10));
     for vault in vaults {
-        let vault_addr = vault.account.to_string().parse::<Address>()?;
+        let account = vault.account;
+        if let Err(err) =
+            start_liquidations_for_vault(context, vault, &mut oracle_response, oracle, dry_run)
+                .await
+        {
+            println!("error liquidating vault {:?}: {}", account, err);
+        }
+    }
+    Ok(())
+}
+
+/// Auctions the vault's collateral one token at a time, re-reading the vault
+/// before each auction because starting one changes its debt per collateral.
+async fn start_liquidations_for_vault(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    vault: Vault,
+    oracle_response: &mut OracleResponse,
+    oracle: &impl PriceOracle,
+    dry_run: bool,
+) -> Result<(), eyre::Error> {
+    let collateral_addr = format!("{:?}", vault.collateral);
+    if context.has_pending(&format!("{:?}", vault.account), &collateral_addr)? {
+        println!(
+            "account {:?} has a start auction waiting to be mined",
+            vault.account
+        );
+        return Ok(());
+    }
+    for (i, token_id) in vault.token_ids.iter().enumerate() {
+        if !dry_run {
+            ensure_valid_oracle_message(context, &collateral_addr, oracle, oracle_response).await?;
+        }
+        let price = oracle_response.price_in_atomic_units()?;
+        let info = context
+            .provider
+            .vault_info(vault.account, vault.collateral)
+            .await?;
+        let now = context.provider.latest_block_timestamp().await?;
+        match vault_status(
+            &info,
+            price,
+            context.controller.max_ltv,
+            context.target,
+            context.auction_min_spacing,
+            now,
+        )? {
+            VaultStatus::NotLiquidatable => {
+                println!("account {:?} is no longer liquidatable", vault.account);
+                break;
+            }
+            VaultStatus::AuctionSpacing { next_start } => {
+                println!(
+                    "{} remaining collateral of account {:?} can be auctioned from {}",
+                    vault.token_ids.len() - i,
+                    vault.account,
+                    next_start
+                );
+                break;
+            }
+            VaultStatus::Liquidatable => {}
+        }
+
         let collateral = Collateral {
-            addr: vault.token.id.parse::<Address>()?,
-            id: U256::from_dec_str(
-                &vault
-                    .collateral
-                    .first()
-                    // should not be possible happen but just incase :)
-                    .ok_or(eyre::eyre!("no collateral in vault"))?
-                    .token_id,
-            )?,
+            addr: vault.collateral,
+            id: *token_id,
         };
         println!(
-            "liquidating collateral {} id {} of account {}",
-            vault.token.id, collateral.id, vault.account
+            "liquidating collateral {} id {} of account {:?}",
+            collateral_addr, collateral.id, vault.account
         );
 
-        if *DISABLE_EXECUTE_START_ACTION {
-            continue;
+        if dry_run {
+            // nothing changes on chain so later collateral would report the same status
+            if vault.token_ids.len() > i + 1 {
+                println!(
+                    "{} more collateral of account {:?} to auction, {} seconds apart",
+                    vault.token_ids.len() - i - 1,
+                    vault.account,
+                    context.auction_min_spacing
+                );
+            }
+            break;
         }
 
-        controller_provider
-            .start_liquidation_auction(
-                vault_addr,
-                collateral,
-                oracle_response.message.as_contract_oracle_info()?,
-            )
-            .await?;
-        println!("liquidation successful");
+        let token_id = collateral.id;
+        let attempt = |outcome, error: Option<String>| AuctionAttempt {
+            timestamp: now,
+            controller: context.controller.id.clone(),
+            vault: format!("{:?}", vault.account),
+            collateral: collateral_addr.clone(),
+            token_id,
+            oracle_price: price,
+            tx_hash: None,
+            nonce: None,
+            raw_tx: None,
+            gas_used: None,
+            auction_id: None,
+            snapshot_block: Some(context.snapshot_block),
+            outcome,
+            error,
+        };
+
+        let oracle_info = oracle_response.message.as_contract_oracle_info()?;
+        match simulate_start_liquidation_auction(
+            context.provider,
+            vault.account,
+            &collateral,
+            &oracle_info,
+        )
+        .await
+        {
+            Ok(()) => {}
+            // specific to this token, the vault's other collateral may still be auctioned
+            Err(SimulationError::Reverted(
+                err @ (PaprControllerABIErrors::AuctionExists(_)
+                | PaprControllerABIErrors::InvalidCollateralAccountPair(_)),
+            )) => {
+                println!("skipping collateral id {}: {:?}", collateral.id, err);
+                context
+                    .journal
+                    .record(&attempt(Outcome::Skipped, Some(format!("{:?}", err))))?;
+                continue;
+            }
+            Err(err) => {
+                println!("skipping account {:?}: {}", vault.account, err);
+                context
+                    .journal
+                    .record(&attempt(Outcome::Skipped, Some(err.to_string())))?;
+                break;
+            }
+        }
+
+        let sent = match context
+            .provider
+            .send_start_liquidation_auction(vault.account, collateral, oracle_info)
+            .await
+        {
+            Ok(sent) => sent,
+            Err(err) => {
+                context
+                    .journal
+                    .record(&attempt(Outcome::Failed, Some(err.to_string())))?;
+                return Err(err);
+            }
+        };
+        println!(
+            "sent start auction {:?} with nonce {}",
+            sent.hash, sent.nonce
+        );
+        // journaled before waiting so a crash cannot lose a sent transaction
+        let sent_attempt = AuctionAttempt {
+            tx_hash: Some(sent.hash),
+            nonce: Some(sent.nonce),
+            raw_tx: Some(sent.raw),
+            ..attempt(Outcome::Sent, None)
+        };
+        context.journal.record(&sent_attempt)?;
+        context
+            .pending
+            .lock()
+            .map_err(|_| eyre::eyre!("pending lock poisoned"))?
+            .push(PendingLiquidation::new(sent_attempt, sent.nonce));
+        // the vault's next auction waits out the spacing after this one, which the
+        // chain only enforces once it is mined
+        break;
+    }
+    Ok(())
+}
+
+/// Waits for the pending transactions to be mined and journals how they
+/// settled. Ones still unmined are left `Sent` for a later pass.
+async fn settle_pending_liquidations(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+) -> Result<(), eyre::Error> {
+    let mut pending = std::mem::take(
+        &mut *context
+            .pending
+            .lock()
+            .map_err(|_| eyre::eyre!("pending lock poisoned"))?,
+    );
+    let mut polls = 0;
+    while !pending.is_empty() {
+        pending = poll_pending_liquidations(context, pending).await?;
+        if pending.is_empty() {
+            break;
+        }
+        if polls == RECEIPT_POLLS {
+            return Err(eyre::eyre!(
+                "{} start auction transactions not mined in time",
+                pending.len()
+            ));
+        }
+        polls += 1;
+        tokio::time::sleep(RECEIPT_POLL_INTERVAL).await;
     }
     Ok(())
 }
 
-fn max_debt(
+/// Checks each pending nonce once, journaling the ones settled, and returns the
+/// rest. Transactions the mempool dropped are rebroadcast, ones unmined for a
+/// while are replaced with higher fees and ones whose nonce another
+/// transaction used are given up on.
+async fn poll_pending_liquidations(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    pending: Vec<PendingLiquidation>,
+) -> Result<Vec<PendingLiquidation>, eyre::Error> {
+    // read before the receipts so a nonce used up here has its receipt below
+    let confirmed_nonce = context.provider.confirmed_nonce().await?;
+    let mut unmined = vec![];
+    for mut liquidation in pending {
+        let nonce = liquidation.nonce;
+        let mut mined = None;
+        for (i, attempt) in liquidation.sent.iter().enumerate() {
+            if let Some(receipt) = context
+                .provider
+                .liquidation_receipt(attempt.tx_hash.unwrap_or_default())
+                .await?
+            {
+                mined = Some((i, receipt));
+                break;
+            }
+        }
+        let latest = liquidation.latest();
+        let hash = latest.tx_hash.unwrap_or_default();
+        if let Some((i, LiquidationReceipt { receipt, auction })) = mined {
+            let attempt = liquidation.sent.remove(i);
+            let error = format!(
+                "nonce {} used by {:?}",
+                nonce,
+                attempt.tx_hash.unwrap_or_default()
+            );
+            record_mined(context, attempt, receipt, auction)?;
+            for replaced in liquidation.sent {
+                record_failed(context, replaced, error.clone())?;
+            }
+        } else if confirmed_nonce > nonce {
+            println!("start auction {:?} was replaced", hash);
+            for attempt in liquidation.sent {
+                record_failed(
+                    context,
+                    attempt,
+                    format!("nonce {} used by another transaction", nonce),
+                )?;
+            }
+            context.provider.reset_nonces().await;
+        } else if context.provider.transaction_known(hash).await? {
+            liquidation.polls += 1;
+            if liquidation.polls >= REPLACE_AFTER_POLLS && liquidation.sent.len() <= FEE_BUMPS {
+                replace_pending_liquidation(context, &mut liquidation).await?;
+            }
+            unmined.push(liquidation);
+        } else if let Some(raw) = latest.raw_tx.clone() {
+            println!("start auction {:?} was dropped, rebroadcasting", hash);
+            context.provider.rebroadcast(raw).await?;
+            unmined.push(liquidation);
+        } else {
+            println!("start auction {:?} was dropped", hash);
+            for attempt in liquidation.sent {
+                record_failed(context, attempt, "dropped from the mempool".to_string())?;
+            }
+            // later transactions would queue behind the unused nonce
+            context.provider.reset_nonces().await;
+        }
+    }
+    Ok(unmined)
+}
+
+/// Sends the latest transaction of the nonce again with higher fees,
+/// journaling the replacement as `Sent` too.
+async fn replace_pending_liquidation(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    liquidation: &mut PendingLiquidation,
+) -> Result<(), eyre::Error> {
+    let latest = liquidation.latest();
+    let Some(raw) = latest.raw_tx.clone() else {
+        return Ok(());
+    };
+    match context.provider.replace(raw).await {
+        Ok(sent) => {
+            println!(
+                "start auction {:?} not mined, replaced by {:?} with higher fees",
+                latest.tx_hash, sent.hash
+            );
+            let replacement = AuctionAttempt {
+                tx_hash: Some(sent.hash),
+                nonce: Some(sent.nonce),
+                raw_tx: Some(sent.raw),
+                ..latest.clone()
+            };
+            context.journal.record(&replacement)?;
+            liquidation.sent.push(replacement);
+            liquidation.polls = 0;
+        }
+        // e.g. mined since its receipt was read, the next poll settles it
+        Err(err) => println!(
+            "error replacing start auction {:?}: {}",
+            latest.tx_hash, err
+        ),
+    }
+    Ok(())
+}
+
+fn record_mined(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    attempt: AuctionAttempt,
+    receipt: TransactionReceipt,
+    auction: Option<StartedAuction>,
+) -> Result<(), eyre::Error> {
+    context.journal.record(&AuctionAttempt {
+        raw_tx: None,
+        gas_used: receipt.gas_used,
+        auction_id: auction.as_ref().map(|a| a.auction_id),
+        outcome: if auction.is_some() {
+            Outcome::Started
+        } else {
+            Outcome::Failed
+        },
+        ..attempt
+    })?;
+    match auction {
+        Some(auction) => println!(
+            "liquidation successful, auction {} start price {}",
+            auction.auction_id, auction.start_price
+        ),
+        None => println!(
+            "start auction transaction {:?} reverted",
+            receipt.transaction_hash
+        ),
+    }
+    Ok(())
+}
+
+fn record_failed(
+    context: &ControllerContext<'_, impl ControllerExecutor>,
+    attempt: AuctionAttempt,
+    error: String,
+) -> Result<(), eyre::Error> {
+    context.journal.record(&AuctionAttempt {
+        raw_tx: None,
+        outcome: Outcome::Failed,
+        error: Some(error),
+        ..attempt
+    })
+}
+
+/// Simulates starting the auction, retrying reverts that may clear up on their own.
+async fn simulate_start_liquidation_auction(
+    provider: &impl ControllerExecutor,
+    account: Address,
+    collateral: &Collateral,
+    oracle_info: &OracleInfo,
+) -> Result<(), SimulationError> {
+    let mut attempt = 1;
+    loop {
+        match provider
+            .simulate_start_liquidation_auction(account, collateral.clone(), oracle_info.clone())
+            .await
+        {
+            Err(err) if err.is_retryable() && attempt < SIMULATION_ATTEMPTS => {
+                println!("simulation {}, retrying", err);
+                tokio::time::sleep(SIMULATION_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Mirrors the checks `startLiquidationAuction` makes before starting an auction.
+fn vault_status(
+    info: &VaultInfo,
+    price: U256,
+    max_ltv: U256,
+    target: U256,
+    auction_min_spacing: U256,
+    now: u64,
+) -> Result<VaultStatus, eyre::Error> {
+    if info.count == 0 {
+        return Ok(VaultStatus::NotLiquidatable);
+    }
+    let collateral_value = price
+        .checked_mul(info.count.into())
+        .ok_or(eyre::eyre!("collateral value overflow"))?;
+    if info.debt < max_debt(collateral_value, max_ltv, target)? {
+        return Ok(VaultStatus::NotLiquidatable);
+    }
+    let next_start = U256::from(info.latest_auction_start_time) + auction_min_spacing;
+    if next_start > now.into() {
+        return Ok(VaultStatus::AuctionSpacing { next_start });
+    }
+    Ok(VaultStatus::Liquidatable)
+}
+
+pub(crate) fn max_debt(
     collateral_value_underlying: U256,
     max_ltv: U256,
     target: U256,
@@ -161,9 +764,540 @@ fn max_debt(
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::needless_return)]
 mod tests {
-    use crate::start::max_debt;
-    use ethers::types::U256;
+    use crate::{
+        config::Config,
+        journal::{AuctionAttempt, Journal, Outcome},
+        local_node::{sign_price, LocalNode},
+        mock_server::{fixture, MockServer, Request},
+        papr_controller::{
+            Collateral, ControllerExecutor, LiquidationReceipt, NotLiquidatable, OracleInfo,
+            PaprController, PaprControllerABICalls, PaprControllerABIErrors, SentTransaction,
+            SimulationError, StartedAuction, VaultInfo,
+        },
+        papr_subgraph::client::GraphQLClient,
+        projection::Funding,
+        reservoir::{
+            client::ReservoirClient,
+            oracle::{OracleResponse, PriceKind, PriceOracle},
+        },
+        start::{
+            max_debt, start_liqudations_for_controller,
+            start_liquidations_for_whitelisted_controllers, vault_status, Pass, VaultStatus,
+            RECEIPT_POLLS, RECEIPT_POLL_INTERVAL, REPLACE_AFTER_POLLS,
+        },
+        vault_source::{Controller, Vault, VaultSource},
+    };
+    use ethers::{
+        abi::{decode, encode, AbiDecode, ParamType, Token},
+        middleware::SignerMiddleware,
+        providers::{Http, Provider},
+        signers::{LocalWallet, Signer},
+        types::{Address, Bytes, TransactionReceipt, H256, U256},
+    };
+    use serde_json::{json, Value};
+    use std::{
+        env, fs, process,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+    use tokio::sync::Semaphore;
+
+    const NOW: u64 = 1674959723;
+    const BLOCK: u64 = 16508300;
+
+    #[tokio::test]
+    async fn starts_one_auction_then_waits_for_spacing() {
+        let ran = Scenario::new("spacing").run().await;
+
+        // the second token has to wait for the auction spacing
+        assert_eq!(ran.token_ids(), vec![U256::from(1)]);
+        assert_eq!(ran.attempts.len(), 2);
+        assert_eq!(ran.attempts[0].outcome, Outcome::Sent);
+        assert_eq!(ran.attempts[0].nonce, Some(U256::zero()));
+        assert_eq!(ran.attempts[1].outcome, Outcome::Started);
+        assert_eq!(ran.attempts[1].tx_hash, ran.attempts[0].tx_hash);
+        assert_eq!(ran.attempts[1].auction_id, Some(U256::from(1)));
+        assert_eq!(ran.attempts[1].snapshot_block, Some(BLOCK));
+    }
+
+    #[tokio::test]
+    async fn entry_point_builds_a_controller_for_each_to_liquidate() {
+        let (journal, path) = journal("entry-point");
+        let config = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            liquidate = true
+
+            [[controllers]]
+            address = "0xd0a830278773282bbf635fd8e47b2447f1e9fe86"
+            purchase = true
+            "#,
+        )
+        .unwrap();
+        let built = Mutex::new(vec![]);
+        start_liquidations_for_whitelisted_controllers(
+            &FakeOracle,
+            &FakeVaults::default(),
+            |address| {
+                built.lock().unwrap().push(address.to_string());
+                Ok(FakeController::default())
+            },
+            &config,
+            &journal,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *built.lock().unwrap(),
+            vec!["0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0".to_string()]
+        );
+        let outcomes: Vec<_> = journal
+            .attempts()
+            .unwrap()
+            .iter()
+            .map(|a| a.outcome)
+            .collect();
+        assert_eq!(outcomes, vec![Outcome::Sent, Outcome::Started]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn starts_nothing_in_dry_run_or_for_another_oracle_signer() {
+        for scenario in [
+            Scenario::new("dry-run").dry_run(),
+            Scenario::new("other-signer").oracle_signer(Address::from_low_u64_be(99)),
+        ] {
+            let ran = scenario.run().await;
+            assert!(ran.token_ids().is_empty());
+            assert!(ran.attempts.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn scans_collateral_of_every_controller_up_to_the_limit() {
+        let oracle = SlowOracle::default();
+        let vaults = FakeVaults {
+            collateral: (1..=3).map(Address::from_low_u64_be).collect(),
+            ..Default::default()
+        };
+        let config = Config::parse(
+            r#"
+            scan_concurrency = 2
+
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            liquidate = true
+
+            [[controllers]]
+            address = "0xd0a830278773282bbf635fd8e47b2447f1e9fe86"
+            liquidate = true
+            "#,
+        )
+        .unwrap();
+        let (journal, path) = journal("concurrency");
+        start_liquidations_for_whitelisted_controllers(
+            &oracle,
+            &vaults,
+            |_| Ok(FakeController::default()),
+            &config,
+            &journal,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(oracle.scans.load(Ordering::SeqCst), 6);
+        assert_eq!(oracle.most_in_flight.load(Ordering::SeqCst), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rebroadcasts_dropped_transaction_until_mined() {
+        let ran = Scenario::new("dropped").mempool(Mempool::Drops).run().await;
+
+        assert_eq!(ran.controller.rebroadcasts.load(Ordering::SeqCst), 1);
+        assert_eq!(ran.attempts.len(), 2);
+        assert_eq!(ran.attempts[1].outcome, Outcome::Started);
+        assert_eq!(ran.attempts[1].tx_hash, Some(H256::from_low_u64_be(1)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn settles_transaction_left_unmined_on_the_next_pass() {
+        let (journal, path) = journal("unmined");
+        let mut controller = FakeController {
+            mempool: Mempool::Stuck,
+            ..Default::default()
+        };
+        let vaults = FakeVaults::default();
+        let unmined = start_liqudations_for_controller(
+            &config(),
+            &journal,
+            &FakeOracle,
+            &vaults,
+            &controller,
+            pass(false),
+        )
+        .await;
+        assert!(unmined.is_err());
+        // replaced with higher fees every `REPLACE_AFTER_POLLS`
+        let attempts = journal.attempts().unwrap();
+        let sent = 1 + (RECEIPT_POLLS / REPLACE_AFTER_POLLS) as usize;
+        assert_eq!(attempts.len(), sent);
+        assert!(attempts.iter().all(|a| a.outcome == Outcome::Sent
+            && a.nonce == Some(U256::zero())
+            && a.raw_tx.is_some()));
+
+        controller.mempool = Mempool::Mines;
+        start_liqudations_for_controller(
+            &config(),
+            &journal,
+            &FakeOracle,
+            &vaults,
+            &controller,
+            pass(false),
+        )
+        .await
+        .unwrap();
+        let attempts = journal.attempts().unwrap();
+        // the second token still waits for the spacing of the settled auction
+        assert_eq!(controller.started.lock().unwrap().len(), 1);
+        assert_eq!(attempts.len(), 2 * sent);
+        assert_eq!(attempts[sent].outcome, Outcome::Started);
+        assert_eq!(attempts[sent].auction_id, Some(U256::from(1)));
+        assert_eq!(attempts[sent].raw_tx, None);
+        for replacement in &attempts[sent + 1..] {
+            assert_eq!(replacement.outcome, Outcome::Failed);
+            assert_eq!(
+                replacement.error.as_deref(),
+                Some(format!("nonce 0 used by {:?}", H256::from_low_u64_be(1)).as_str())
+            );
+        }
+        assert!(journal.unsettled(&vaults.controller.id).unwrap().is_empty());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replaces_stuck_transaction_with_higher_fees() {
+        let ran = Scenario::new("underpriced")
+            .mempool(Mempool::Underpriced)
+            .run()
+            .await;
+
+        let replacement = H256::from_low_u64_be(REPLACEMENTS + 1);
+        assert_eq!(*ran.controller.replaced.lock().unwrap(), vec![1]);
+        let settled: Vec<_> = ran
+            .attempts
+            .iter()
+            .map(|a| (a.outcome, a.tx_hash.unwrap()))
+            .collect();
+        assert_eq!(
+            settled,
+            vec![
+                (Outcome::Sent, H256::from_low_u64_be(1)),
+                (Outcome::Sent, replacement),
+                (Outcome::Started, replacement),
+                (Outcome::Failed, H256::from_low_u64_be(1)),
+            ]
+        );
+        assert_eq!(ran.attempts[1].nonce, Some(U256::zero()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stuck_transaction_from_an_earlier_pass_does_not_hold_up_the_scan() {
+        let (journal, path) = journal("stuck");
+        let controller = FakeController {
+            mempool: Mempool::Stuck,
+            ..Default::default()
+        };
+        let vaults = FakeVaults::default();
+        let config = config();
+        for _ in 0..2 {
+            *controller.info.lock().unwrap() = vault_info(2, 0, "1500000000000000000");
+            let unmined = start_liqudations_for_controller(
+                &config,
+                &journal,
+                &FakeOracle,
+                &vaults,
+                &controller,
+                pass(false),
+            )
+            .await;
+            assert!(unmined.is_err());
+        }
+        // the vault was not auctioned again while its auction is pending
+        assert_eq!(controller.started.lock().unwrap().len(), 1);
+
+        let mut other = FakeVaults::default();
+        other.vault.account = Address::from_low_u64_be(11);
+        let before = journal.attempts().unwrap().len();
+        let started = tokio::time::Instant::now();
+        let unmined = start_liqudations_for_controller(
+            &config,
+            &journal,
+            &FakeOracle,
+            &other,
+            &controller,
+            pass(false),
+        )
+        .await;
+        assert!(unmined.is_err());
+        let attempts = journal.attempts().unwrap();
+        assert_eq!(attempts[before].outcome, Outcome::Sent);
+        assert_eq!(
+            attempts[before].vault,
+            format!("{:?}", Address::from_low_u64_be(11))
+        );
+        // the earlier transaction is waited on after the scan, as long as the new one
+        assert_eq!(started.elapsed(), RECEIPT_POLL_INTERVAL * RECEIPT_POLLS);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_on_dropped_transaction_it_cannot_rebroadcast() {
+        let (journal, path) = journal("gap");
+        let vaults = FakeVaults::default();
+        // sent by a version that did not journal signed transactions
+        let dropped = AuctionAttempt {
+            timestamp: NOW,
+            controller: vaults.controller.id.clone(),
+            vault: format!("{:?}", vaults.vault.account),
+            collateral: format!("{:?}", collection()),
+            token_id: U256::from(1),
+            oracle_price: one(),
+            tx_hash: Some(H256::from_low_u64_be(99)),
+            nonce: Some(U256::zero()),
+            raw_tx: None,
+            gas_used: None,
+            auction_id: None,
+            snapshot_block: Some(BLOCK),
+            outcome: Outcome::Sent,
+            error: None,
+        };
+        journal.record(&dropped).unwrap();
+        let controller = FakeController {
+            mempool: Mempool::Drops,
+            ..Default::default()
+        };
+        start_liqudations_for_controller(
+            &config(),
+            &journal,
+            &FakeOracle,
+            &vaults,
+            &controller,
+            pass(false),
+        )
+        .await
+        .unwrap();
+
+        let attempts = journal.attempts().unwrap();
+        assert_eq!(attempts[1].outcome, Outcome::Failed);
+        assert_eq!(attempts[1].tx_hash, dropped.tx_hash);
+        assert_eq!(
+            attempts[1].error.as_deref(),
+            Some("dropped from the mempool")
+        );
+        // the nonce is counted again, so the scan's transaction fills the gap
+        assert_eq!(controller.nonce_resets.load(Ordering::SeqCst), 1);
+        assert_eq!(attempts.last().unwrap().outcome, Outcome::Started);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn records_failure_when_nonce_is_replaced() {
+        let ran = Scenario::new("replaced")
+            .mempool(Mempool::Replaces)
+            .run()
+            .await;
+
+        assert_eq!(ran.attempts.len(), 2);
+        assert_eq!(ran.attempts[1].outcome, Outcome::Failed);
+        assert_eq!(
+            ran.attempts[1].error.as_deref(),
+            Some("nonce 0 used by another transaction")
+        );
+        assert_eq!(ran.controller.nonce_resets.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn records_skip_when_simulation_reverts() {
+        let ran = Scenario::new("revert").reverting().run().await;
+
+        assert!(ran.token_ids().is_empty());
+        assert_eq!(ran.attempts.len(), 1);
+        assert_eq!(ran.attempts[0].outcome, Outcome::Skipped);
+        assert_eq!(
+            ran.attempts[0].error.as_deref(),
+            Some("reverted with NotLiquidatable(NotLiquidatable)")
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_against_mock_services_checks_liquidatable_vault() {
+        let rpc = MockServer::start(rpc_response);
+        let subgraph = MockServer::start(|request| {
+            let body = request.json();
+            let response = match body["operationName"].as_str()? {
+                "SubgraphMeta" => include_str!("fixtures/subgraph_meta.json"),
+                "ControllerByID" if body["variables"]["id"] == MISSING_CONTROLLER => {
+                    r#"{"data": {"paprController": null}}"#
+                }
+                "ControllerByID" => include_str!("fixtures/subgraph_controller.json"),
+                "CollateralByController" => include_str!("fixtures/subgraph_collateral.json"),
+                "VaultsExceedingDebtPerCollateral" => include_str!("fixtures/subgraph_vaults.json"),
+                _ => return None,
+            };
+            Some(fixture(response))
+        });
+        let reservoir = MockServer::start(|request| {
+            (request.path == "/oracle/collections/top-bid/v2")
+                .then(|| fixture(include_str!("fixtures/reservoir_top_bid.json")))
+        });
+        let config = Config::parse(CONFIG).unwrap();
+        let (journal, path) = journal("mock-services");
+        let built = Mutex::new(vec![]);
+
+        start_liquidations_for_whitelisted_controllers(
+            &ReservoirClient::new(reservoir.url(), "test-key"),
+            &GraphQLClient::new(subgraph.url()).with_node(&rpc.url()),
+            |address| {
+                built.lock().unwrap().push(address.to_string());
+                Ok(mock_controller(address, &rpc))
+            },
+            &config,
+            &journal,
+            true,
+        )
+        .await
+        .unwrap();
+
+        // only controllers configured to liquidate are built, and the one
+        // missing from the subgraph does not stop the other
+        let mut built = built.into_inner().unwrap();
+        built.sort();
+        assert_eq!(
+            built,
+            vec![
+                MISSING_CONTROLLER.to_string(),
+                "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0".to_string()
+            ]
+        );
+        // the subgraph's lag was checked against the node's head
+        assert!(rpc
+            .requests()
+            .iter()
+            .any(|r| r.json()["method"] == "eth_blockNumber"));
+        let requests: Vec<Request> = subgraph
+            .requests()
+            .into_iter()
+            .filter(|r| r.json()["variables"]["id"] != MISSING_CONTROLLER)
+            .collect();
+        let operations: Vec<Value> = requests
+            .iter()
+            .map(|r| r.json()["operationName"].clone())
+            .collect();
+        assert_eq!(
+            operations,
+            vec![
+                "SubgraphMeta",
+                "ControllerByID",
+                "CollateralByController",
+                "VaultsExceedingDebtPerCollateral"
+            ]
+        );
+        // every read after the health check is pinned to the indexed block
+        for request in &requests[1..] {
+            assert_eq!(request.json()["variables"]["block"]["number"], 16508300);
+        }
+        // 64.7 WETH at 50% LTV and a target of 1
+        assert_eq!(
+            requests[3].json()["variables"]["debtPerCollateral"],
+            "32350000000000000000"
+        );
+        assert_eq!(
+            reservoir.requests()[0].query_param("currency"),
+            Some("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")
+        );
+        // the vault was re-read on chain but nothing was sent
+        let calls: Vec<PaprControllerABICalls> = rpc
+            .requests()
+            .iter()
+            .filter_map(|r| call_data(&r.json()))
+            .collect();
+        assert!(calls
+            .iter()
+            .any(|call| matches!(call, PaprControllerABICalls::VaultInfo(_))));
+        assert!(rpc
+            .requests()
+            .iter()
+            .all(|r| !r.json()["method"].as_str().unwrap().starts_with("eth_send")));
+        assert!(journal.attempts().unwrap().is_empty());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil and the state from scripts/anvil-state.sh"]
+    async fn starts_auction_for_underwater_vault_on_local_node() {
+        let node = LocalNode::spawn().unwrap();
+        let controller = node.controller_params().await.unwrap();
+        let one_underlying = U256::exp10(controller.underlying_decimals as usize);
+        let token_id = U256::from(1);
+        node.open_vault(1, token_id, one_underlying * 10)
+            .await
+            .unwrap();
+        let bot = PaprController::with_client(&controller.id, node.client(2), Default::default())
+            .unwrap();
+        let vaults = FakeVaults {
+            controller: controller.clone(),
+            collateral: vec![node.collateral],
+            vault: Vault {
+                account: node.client(1).address(),
+                collateral: node.collateral,
+                token_ids: vec![token_id],
+            },
+        };
+        let (journal, path) = journal("local-node");
+
+        // the collateral halves in value
+        start_liqudations_for_controller(
+            &Config::parse(&format!(
+                "[[controllers]]\naddress = \"{}\"\nliquidate = true",
+                controller.id
+            ))
+            .unwrap()
+            .controllers
+            .remove(0),
+            &journal,
+            &LocalOracle {
+                node: &node,
+                price: 5.0,
+                atomic_price: one_underlying * 5,
+            },
+            &vaults,
+            &bot,
+            pass(false),
+        )
+        .await
+        .unwrap();
+
+        let attempts = journal.attempts().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[1].outcome, Outcome::Started);
+        assert!(attempts[1].auction_id.is_some());
+        let info = bot
+            .vault_info(node.client(1).address(), node.collateral)
+            .await
+            .unwrap();
+        assert_eq!(info.count, 0);
+        fs::remove_file(&path).unwrap();
+    }
 
     #[test]
     fn max_debt_correctly_computes() {
@@ -204,7 +1338,582 @@ mod tests {
         assert_eq!("max_debt divide by 0", result.err().unwrap().to_string());
     }
 
+    #[test]
+    fn vault_status_not_liquidatable_below_max_debt() {
+        // 2 NFTs worth 1 each at 50% LTV and target 1 => max debt 1
+        let info = vault_info(2, 0, "999999999999999999");
+        let status =
+            vault_status(&info, one(), half(), one(), U256::from(172800), 1000000).unwrap();
+        assert_eq!(status, VaultStatus::NotLiquidatable);
+    }
+
+    #[test]
+    fn vault_status_liquidatable_at_max_debt() {
+        let info = vault_info(2, 0, "1000000000000000000");
+        let status =
+            vault_status(&info, one(), half(), one(), U256::from(172800), 1000000).unwrap();
+        assert_eq!(status, VaultStatus::Liquidatable);
+    }
+
+    #[test]
+    fn vault_status_waits_for_auction_spacing() {
+        // one NFT already auctioned 100 seconds ago, the remaining one is now underwater
+        let info = vault_info(1, 999900, "1000000000000000000");
+        let status =
+            vault_status(&info, one(), half(), one(), U256::from(172800), 1000000).unwrap();
+        assert_eq!(
+            status,
+            VaultStatus::AuctionSpacing {
+                next_start: U256::from(999900 + 172800)
+            }
+        );
+    }
+
+    #[test]
+    fn vault_status_not_liquidatable_without_collateral() {
+        let info = vault_info(0, 0, "1000000000000000000");
+        let status =
+            vault_status(&info, one(), half(), one(), U256::from(172800), 1000000).unwrap();
+        assert_eq!(status, VaultStatus::NotLiquidatable);
+    }
+
+    fn vault_info(count: u16, latest_auction_start_time: u64, debt: &str) -> VaultInfo {
+        VaultInfo {
+            count,
+            auction_count: 0,
+            latest_auction_start_time,
+            debt: u256_from_str(debt),
+        }
+    }
+
+    fn one() -> U256 {
+        u256_from_str("1000000000000000000")
+    }
+
+    fn half() -> U256 {
+        u256_from_str("500000000000000000")
+    }
+
     fn u256_from_str(i: &str) -> U256 {
         return U256::from_dec_str(i).unwrap();
     }
+
+    fn config() -> crate::config::ControllerConfig {
+        Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            liquidate = true
+            "#,
+        )
+        .unwrap()
+        .controllers
+        .remove(0)
+    }
+
+    fn journal(name: &str) -> (Journal, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!(
+            "auction-bot-start-{}-{}.jsonl",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        (Journal::open(&path).unwrap(), path)
+    }
+
+    fn pass(dry_run: bool) -> Pass {
+        Pass {
+            snapshot_block: BLOCK,
+            scans: Arc::new(Semaphore::new(1)),
+            dry_run,
+        }
+    }
+
+    fn collection() -> Address {
+        Address::from_low_u64_be(1)
+    }
+
+    /// One pass over the fake controller and vaults, journaling to a temp file
+    struct Scenario {
+        name: &'static str,
+        controller: FakeController,
+        vaults: FakeVaults,
+        pass: Pass,
+    }
+
+    /// What a scenario's pass did
+    struct Ran {
+        controller: FakeController,
+        attempts: Vec<AuctionAttempt>,
+    }
+
+    impl Scenario {
+        fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                controller: FakeController::default(),
+                vaults: FakeVaults::default(),
+                pass: pass(false),
+            }
+        }
+
+        fn dry_run(mut self) -> Self {
+            self.pass.dry_run = true;
+            self
+        }
+
+        fn reverting(mut self) -> Self {
+            self.controller.reverts = true;
+            self
+        }
+
+        fn mempool(mut self, mempool: Mempool) -> Self {
+            self.controller.mempool = mempool;
+            self
+        }
+
+        fn oracle_signer(mut self, oracle_signer: Address) -> Self {
+            self.controller.oracle_signer = oracle_signer;
+            self
+        }
+
+        async fn run(self) -> Ran {
+            self.run_with(&FakeOracle).await
+        }
+
+        async fn run_with(self, oracle: &impl PriceOracle) -> Ran {
+            let (journal, path) = journal(self.name);
+            start_liqudations_for_controller(
+                &config(),
+                &journal,
+                oracle,
+                &self.vaults,
+                &self.controller,
+                self.pass,
+            )
+            .await
+            .unwrap();
+            let attempts = journal.attempts().unwrap();
+            let _ = fs::remove_file(&path);
+            Ran {
+                controller: self.controller,
+                attempts,
+            }
+        }
+    }
+
+    impl Ran {
+        /// token ids of the sent transactions, in order
+        fn token_ids(&self) -> Vec<U256> {
+            self.controller
+                .started
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, c)| c.id)
+                .collect()
+        }
+    }
+
+    /// Serves one controller with one vault
+    struct FakeVaults {
+        controller: Controller,
+        collateral: Vec<Address>,
+        vault: Vault,
+    }
+
+    impl Default for FakeVaults {
+        /// A vault holding two tokens of one collection
+        fn default() -> Self {
+            Self {
+                controller: Controller {
+                    id: "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0".to_string(),
+                    max_ltv: half(),
+                    underlying: format!("{:?}", Address::from_low_u64_be(2)),
+                    underlying_decimals: 18,
+                },
+                collateral: vec![collection()],
+                vault: Vault {
+                    account: Address::from_low_u64_be(10),
+                    collateral: collection(),
+                    token_ids: vec![U256::from(1), U256::from(2)],
+                },
+            }
+        }
+    }
+
+    impl VaultSource for FakeVaults {
+        async fn snapshot_block(&self, _max_lag_blocks: u64) -> Result<u64, eyre::Error> {
+            Ok(BLOCK)
+        }
+
+        async fn controller(&self, _id: &str, _block: u64) -> Result<Controller, eyre::Error> {
+            Ok(self.controller.clone())
+        }
+
+        async fn collateral(
+            &self,
+            _controller: &str,
+            _block: u64,
+        ) -> Result<Vec<String>, eyre::Error> {
+            Ok(self
+                .collateral
+                .iter()
+                .map(|collateral| format!("{:?}", collateral))
+                .collect())
+        }
+
+        async fn vaults_exceeding_debt_per_collateral(
+            &self,
+            _controller: &str,
+            _collateral: &str,
+            _debt_per_collateral: U256,
+            _latest_auction_before: u64,
+            _block: u64,
+        ) -> Result<Vec<Vault>, eyre::Error> {
+            Ok(vec![self.vault.clone()])
+        }
+    }
+
+    /// Prices every collection at 1 with a message signed at `NOW` by `oracle_wallet`
+    struct FakeOracle;
+
+    impl PriceOracle for FakeOracle {
+        async fn max_collection_bid(
+            &self,
+            collection: &str,
+            _price_kind: PriceKind,
+            quote_currency: &str,
+            _twap_seconds: Option<u32>,
+        ) -> Result<OracleResponse, eyre::Error> {
+            Ok(OracleResponse {
+                price: 1.0,
+                message: sign_price(
+                    &oracle_wallet(),
+                    collection.parse()?,
+                    quote_currency.parse()?,
+                    one(),
+                    NOW,
+                ),
+            })
+        }
+    }
+
+    /// Anvil account 0, the oracle signer of the fake and mocked controllers
+    fn oracle_wallet() -> LocalWallet {
+        "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+            .parse()
+            .unwrap()
+    }
+
+    /// `FakeOracle` answering slowly, counting the most requests in flight at once
+    #[derive(Default)]
+    struct SlowOracle {
+        scans: AtomicUsize,
+        in_flight: AtomicUsize,
+        most_in_flight: AtomicUsize,
+    }
+
+    impl PriceOracle for SlowOracle {
+        async fn max_collection_bid(
+            &self,
+            collection: &str,
+            price_kind: PriceKind,
+            quote_currency: &str,
+            twap_seconds: Option<u32>,
+        ) -> Result<OracleResponse, eyre::Error> {
+            self.scans.fetch_add(1, Ordering::SeqCst);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            FakeOracle
+                .max_collection_bid(collection, price_kind, quote_currency, twap_seconds)
+                .await
+        }
+    }
+
+    /// Prices in the controller's underlying signed by the local node's oracle
+    /// signer at its latest block
+    struct LocalOracle<'a> {
+        node: &'a LocalNode,
+        price: f64,
+        atomic_price: U256,
+    }
+
+    impl PriceOracle for LocalOracle<'_> {
+        async fn max_collection_bid(
+            &self,
+            collection: &str,
+            _price_kind: PriceKind,
+            quote_currency: &str,
+            _twap_seconds: Option<u32>,
+        ) -> Result<OracleResponse, eyre::Error> {
+            Ok(OracleResponse {
+                price: self.price,
+                message: sign_price(
+                    &self.node.oracle_signer(),
+                    collection.parse()?,
+                    quote_currency.parse()?,
+                    self.atomic_price,
+                    self.node.latest_timestamp().await?,
+                ),
+            })
+        }
+    }
+
+    /// A controller at target 1 whose vault is 1.5 in debt against its two
+    /// tokens, above the max debt of 1 at 50% LTV
+    struct FakeController {
+        reverts: bool,
+        oracle_signer: Address,
+        mempool: Mempool,
+        info: Mutex<VaultInfo>,
+        /// collateral of each sent transaction, hashed by its position from 1
+        started: Mutex<Vec<(Address, Collateral)>>,
+        rebroadcasts: AtomicUsize,
+        /// position of the transaction each replacement replaces, hashed by its
+        /// position from `REPLACEMENTS + 1`
+        replaced: Mutex<Vec<u64>>,
+        nonce_resets: AtomicUsize,
+    }
+
+    const REPLACEMENTS: u64 = 1000;
+
+    /// The fake's signed transaction, the position it was started at and its nonce
+    fn fake_raw(position: u64, nonce: U256) -> Bytes {
+        encode(&[Token::Uint(position.into()), Token::Uint(nonce)]).into()
+    }
+
+    /// What happens to sent transactions
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mempool {
+        Mines,
+        /// forgotten until rebroadcast, then mined
+        Drops,
+        /// another transaction of the signer takes the nonce
+        Replaces,
+        /// kept in the mempool without being mined
+        Stuck,
+        /// kept in the mempool until replaced with higher fees, then mined
+        Underpriced,
+    }
+
+    impl Default for FakeController {
+        fn default() -> Self {
+            Self {
+                reverts: false,
+                oracle_signer: oracle_wallet().address(),
+                mempool: Mempool::Mines,
+                info: Mutex::new(vault_info(2, 0, "1500000000000000000")),
+                started: Mutex::new(vec![]),
+                rebroadcasts: AtomicUsize::new(0),
+                replaced: Mutex::new(vec![]),
+                nonce_resets: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl ControllerExecutor for FakeController {
+        async fn latest_block_timestamp(&self) -> Result<u64, eyre::Error> {
+            Ok(NOW)
+        }
+
+        async fn oracle_signer(&self) -> Result<Address, eyre::Error> {
+            Ok(self.oracle_signer)
+        }
+
+        async fn new_target(&self) -> Result<U256, eyre::Error> {
+            Ok(one())
+        }
+
+        async fn funding(&self) -> Result<Funding, eyre::Error> {
+            Ok(Funding {
+                target: one(),
+                mark: one(),
+                funding_period: U256::from(2419200),
+                last_updated: NOW,
+            })
+        }
+
+        async fn liquidation_auction_min_spacing(&self) -> Result<U256, eyre::Error> {
+            Ok(U256::from(172800))
+        }
+
+        async fn vault_info(
+            &self,
+            _account: Address,
+            _asset: Address,
+        ) -> Result<VaultInfo, eyre::Error> {
+            Ok(self.info.lock().unwrap().clone())
+        }
+
+        async fn simulate_start_liquidation_auction(
+            &self,
+            _account: Address,
+            _collateral: Collateral,
+            _oracle_info: OracleInfo,
+        ) -> Result<(), SimulationError> {
+            if self.reverts {
+                return Err(SimulationError::Reverted(
+                    PaprControllerABIErrors::NotLiquidatable(NotLiquidatable),
+                ));
+            }
+            Ok(())
+        }
+
+        async fn send_start_liquidation_auction(
+            &self,
+            account: Address,
+            collateral: Collateral,
+            _oracle_info: OracleInfo,
+        ) -> Result<SentTransaction, eyre::Error> {
+            let mut info = self.info.lock().unwrap();
+            info.count -= 1;
+            info.latest_auction_start_time = NOW;
+            let mut started = self.started.lock().unwrap();
+            started.push((account, collateral));
+            let (position, nonce) = (started.len() as u64, U256::from(started.len() - 1));
+            Ok(SentTransaction {
+                hash: H256::from_low_u64_be(position),
+                nonce,
+                raw: fake_raw(position, nonce),
+            })
+        }
+
+        async fn liquidation_receipt(
+            &self,
+            tx_hash: H256,
+        ) -> Result<Option<LiquidationReceipt>, eyre::Error> {
+            let auction_id = tx_hash.to_low_u64_be();
+            let mined = match self.mempool {
+                Mempool::Mines => true,
+                Mempool::Drops => self.rebroadcasts.load(Ordering::SeqCst) > 0,
+                Mempool::Replaces | Mempool::Stuck => false,
+                Mempool::Underpriced => auction_id > REPLACEMENTS,
+            };
+            if !mined {
+                return Ok(None);
+            }
+            let position = match auction_id.checked_sub(REPLACEMENTS + 1) {
+                Some(replacement) => self.replaced.lock().unwrap()[replacement as usize],
+                None => auction_id,
+            };
+            let (account, collateral) = self.started.lock().unwrap()[position as usize - 1].clone();
+            Ok(Some(LiquidationReceipt {
+                receipt: TransactionReceipt {
+                    transaction_hash: tx_hash,
+                    ..Default::default()
+                },
+                auction: Some(StartedAuction {
+                    auction_id: auction_id.into(),
+                    nft: collateral,
+                    nft_owner: account,
+                    start_price: one(),
+                    per_period_decay_percent_wad: half(),
+                    seconds_in_period: U256::from(86400),
+                    payment_asset: Address::zero(),
+                }),
+            }))
+        }
+
+        async fn confirmed_nonce(&self) -> Result<U256, eyre::Error> {
+            match self.mempool {
+                Mempool::Replaces => Ok(self.started.lock().unwrap().len().into()),
+                _ => Ok(U256::zero()),
+            }
+        }
+
+        async fn transaction_known(&self, _tx_hash: H256) -> Result<bool, eyre::Error> {
+            Ok(self.mempool != Mempool::Drops || self.rebroadcasts.load(Ordering::SeqCst) > 0)
+        }
+
+        async fn rebroadcast(&self, _raw: Bytes) -> Result<(), eyre::Error> {
+            self.rebroadcasts.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn replace(&self, raw: Bytes) -> Result<SentTransaction, eyre::Error> {
+            let decoded = decode(&[ParamType::Uint(256), ParamType::Uint(256)], &raw)?;
+            let [Token::Uint(position), Token::Uint(nonce)] = decoded[..] else {
+                return Err(eyre::eyre!("not a fake transaction"));
+            };
+            let mut replaced = self.replaced.lock().unwrap();
+            replaced.push(position.as_u64());
+            Ok(SentTransaction {
+                hash: H256::from_low_u64_be(REPLACEMENTS + replaced.len() as u64),
+                nonce,
+                raw: fake_raw(position.as_u64(), nonce),
+            })
+        }
+
+        async fn reset_nonces(&self) {
+            self.nonce_resets.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    const CONFIG: &str = r#"
+        [[controllers]]
+        name = "paprMeme"
+        address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+        liquidate = true
+
+        [[controllers]]
+        name = "paprHero"
+        address = "0xd0a830278773282bbf635fd8e47b2447f1e9fe86"
+        purchase = true
+
+        [[controllers]]
+        address = "0x0000000000000000000000000000000000c0ffee"
+        liquidate = true
+        "#;
+
+    /// A controller to liquidate the subgraph does not know
+    const MISSING_CONTROLLER: &str = "0x0000000000000000000000000000000000c0ffee";
+
+    /// The controller at `address` on the mocked node
+    fn mock_controller(address: &str, rpc: &MockServer) -> PaprController {
+        let provider = Provider::<Http>::try_from(rpc.url()).unwrap();
+        let client = SignerMiddleware::new(provider, oracle_wallet().with_chain_id(1u64));
+        PaprController::with_client(address, Arc::new(client), Default::default()).unwrap()
+    }
+
+    /// A chain 5 blocks ahead of the subgraph where the vault from the subgraph
+    /// fixture holds 2 tokens against 70 papr of debt
+    fn rpc_response(request: &Request) -> Option<Value> {
+        let body = request.json();
+        let result = match body["method"].as_str()? {
+            "eth_blockNumber" => json!("0xfbe591"),
+            "eth_getBlockByNumber" => fixture(include_str!("fixtures/rpc_latest_block.json")),
+            "eth_call" => {
+                let output = match call_data(&body)? {
+                    PaprControllerABICalls::NewTarget(_) => encode(&[Token::Uint(one())]),
+                    PaprControllerABICalls::OracleSigner(_) => {
+                        encode(&[Token::Address(oracle_wallet().address())])
+                    }
+                    PaprControllerABICalls::LiquidationAuctionMinSpacing(_) => {
+                        encode(&[Token::Uint(U256::from(172800))])
+                    }
+                    PaprControllerABICalls::VaultInfo(_) => encode(&[
+                        Token::Uint(U256::from(2)),
+                        Token::Uint(U256::zero()),
+                        Token::Uint(U256::zero()),
+                        Token::Uint(u256_from_str("70000000000000000000")),
+                    ]),
+                    _ => return None,
+                };
+                json!(Bytes::from(output))
+            }
+            _ => return None,
+        };
+        Some(json!({ "jsonrpc": "2.0", "id": body["id"], "result": result }))
+    }
+
+    fn call_data(body: &Value) -> Option<PaprControllerABICalls> {
+        if body["method"] != "eth_call" {
+            return None;
+        }
+        let data: Bytes = body["params"][0]["data"].as_str()?.parse().ok()?;
+        PaprControllerABICalls::decode(data).ok()
+    }
 }
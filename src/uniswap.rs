@@ -0,0 +1,99 @@
+use crate::{erc20, papr_controller::Client};
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, BlockNumber, U256},
+};
+use once_cell::sync::Lazy;
+use std::{env, sync::Arc};
+
+abigen!(UniswapV3QuoterV2, "src/abis/UniswapV3QuoterV2.json");
+abigen!(UniswapV3Pool, "src/abis/UniswapV3Pool.json");
+abigen!(UniswapV3SwapRouter, "src/abis/UniswapV3SwapRouter.json");
+
+/// QuoterV2 is deployed at the same address on mainnet and goerli
+static QUOTER_ADDRESS: Lazy<String> = Lazy::new(|| {
+    env::var("UNISWAP_QUOTER").unwrap_or("0x61fFE014bA17989E743c5F6cB21bF9697530B21e".to_string())
+});
+
+/// SwapRouter is deployed at the same address on mainnet and goerli
+static SWAP_ROUTER_ADDRESS: Lazy<String> = Lazy::new(|| {
+    env::var("UNISWAP_SWAP_ROUTER")
+        .unwrap_or("0xE592427A0AEce92De3Edee1F18E0157C05861564".to_string())
+});
+
+/// Seconds a swap may wait in the mempool before the router rejects it
+const SWAP_DEADLINE_SECONDS: u64 = 300;
+
+/// Amount of `token_in` it costs to buy `amount_out` of `token_out` from `pool`.
+pub async fn quote_exact_output(
+    client: &Arc<Client>,
+    pool: Address,
+    token_in: Address,
+    token_out: Address,
+    amount_out: U256,
+) -> Result<U256, eyre::Error> {
+    let fee = pool_fee(client, pool).await?;
+    let quoter = UniswapV3QuoterV2::new(QUOTER_ADDRESS.parse::<Address>()?, Arc::clone(client));
+    let (amount_in, _, _, _) = quoter
+        .quote_exact_output_single(QuoteExactOutputSingleParams {
+            token_in,
+            token_out,
+            amount: amount_out,
+            fee,
+            sqrt_price_limit_x96: U256::zero(),
+        })
+        .call()
+        .await?;
+    Ok(amount_in)
+}
+
+/// Buys exactly `amount_out` of `token_out` from `pool` for `client`'s signer,
+/// paying at most `max_amount_in` of `token_in`. Returns the amount paid.
+pub async fn swap_exact_output(
+    client: &Arc<Client>,
+    pool: Address,
+    token_in: Address,
+    token_out: Address,
+    amount_out: U256,
+    max_amount_in: U256,
+) -> Result<U256, eyre::Error> {
+    let router_address = SWAP_ROUTER_ADDRESS.parse::<Address>()?;
+    erc20::ensure_allowance(client, token_in, router_address, max_amount_in).await?;
+    let latest = client
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or(eyre::eyre!("latest block not found"))?;
+    let params = ExactOutputSingleParams {
+        token_in,
+        token_out,
+        fee: pool_fee(client, pool).await?,
+        recipient: client.address(),
+        deadline: latest.timestamp + SWAP_DEADLINE_SECONDS,
+        amount_out,
+        amount_in_maximum: max_amount_in,
+        sqrt_price_limit_x96: U256::zero(),
+    };
+    let router = UniswapV3SwapRouter::new(router_address, Arc::clone(client));
+    let call = router.exact_output_single(params);
+    let amount_in = call.call().await?;
+    let receipt = call
+        .send()
+        .await?
+        .await?
+        .ok_or(eyre::eyre!("swap no transaction receipt"))?;
+    if receipt.status != Some(1.into()) {
+        return Err(eyre::eyre!(
+            "swap transaction {:?} reverted",
+            receipt.transaction_hash
+        ));
+    }
+    Ok(amount_in)
+}
+
+async fn pool_fee(client: &Arc<Client>, pool: Address) -> Result<u32, eyre::Error> {
+    Ok(UniswapV3Pool::new(pool, Arc::clone(client))
+        .fee()
+        .call()
+        .await?)
+}
@@ -0,0 +1,334 @@
+use crate::{
+    config::Config,
+    erc20::ERC20,
+    papr_controller::{
+        AddCollateralFilter, AllowCollateralFilter, PaprControllerABI, PaprControllerABIEvents,
+        RemoveCollateralFilter, StartAuctionFilter,
+    },
+    provider::{latest_block_number, PROVIDER},
+    vault_source::{Controller, Vault, VaultSource},
+};
+use ethers::{
+    abi::RawLog,
+    contract::{EthEvent, EthLogDecode},
+    providers::Middleware,
+    types::{Address, Filter, U256},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Most blocks asked for in one `eth_getLogs`, providers cap the range they serve
+const LOG_RANGE_BLOCKS: u64 = 10_000;
+
+/// Which vault holds each NFT, replayed from a controller's events. Debt is not
+/// tracked here, it is read from `vaultInfo` for the vaults that hold collateral,
+/// and `EndAuction` changes nothing as the NFT already left at `StartAuction`.
+#[derive(Clone, Default)]
+struct Holdings {
+    /// last block whose events have been applied
+    scanned_to: Option<u64>,
+    /// every collection the controller has allowed or disallowed
+    collateral: BTreeSet<Address>,
+    /// vault account holding each (collection, token id)
+    owners: HashMap<(Address, U256), Address>,
+}
+
+impl Holdings {
+    fn apply(&mut self, event: PaprControllerABIEvents) {
+        match event {
+            PaprControllerABIEvents::AllowCollateralFilter(e) => {
+                self.collateral.insert(e.collateral);
+            }
+            PaprControllerABIEvents::AddCollateralFilter(e) => {
+                self.owners
+                    .insert((e.collateral_address, e.token_id), e.account);
+            }
+            PaprControllerABIEvents::RemoveCollateralFilter(e) => {
+                self.owners.remove(&(e.collateral_address, e.token_id));
+            }
+            // the auctioned NFT leaves the vault
+            PaprControllerABIEvents::StartAuctionFilter(e) => {
+                self.owners
+                    .remove(&(e.auction_asset_contract, e.auction_asset_id));
+            }
+            _ => {}
+        }
+    }
+
+    /// Token ids of `collateral` held by each vault account
+    fn vaults(&self, collateral: Address) -> BTreeMap<Address, Vec<U256>> {
+        let mut vaults: BTreeMap<Address, Vec<U256>> = BTreeMap::new();
+        for ((asset, token_id), account) in &self.owners {
+            if *asset == collateral {
+                vaults.entry(*account).or_default().push(*token_id);
+            }
+        }
+        for token_ids in vaults.values_mut() {
+            token_ids.sort();
+        }
+        vaults
+    }
+}
+
+/// Finds vaults from the controllers' events and `vaultInfo` over RPC, for when
+/// the subgraph is unavailable. Holdings are cached so later passes only replay
+/// the events since the previous one.
+pub struct ChainVaultSource {
+    /// block each controller was deployed at, where replaying its events starts
+    deployed_at: HashMap<String, u64>,
+    /// locked while replaying, so concurrent scans of a controller's collateral
+    /// wait for one replay instead of each fetching the same logs
+    holdings: Mutex<HashMap<String, Arc<AsyncMutex<Holdings>>>>,
+}
+
+impl ChainVaultSource {
+    /// Fails unless every controller the bot liquidates for has a
+    /// `deployed_at_block`, replaying from genesis takes thousands of requests.
+    pub fn new(config: &Config) -> Result<Self, eyre::Error> {
+        let mut deployed_at = HashMap::new();
+        for controller in &config.controllers {
+            match controller.deployed_at_block {
+                Some(block) => {
+                    deployed_at.insert(controller.address.clone(), block);
+                }
+                None if controller.liquidate => {
+                    return Err(eyre::eyre!(
+                        "controller {} needs deployed_at_block to find vaults on chain",
+                        controller.address
+                    ))
+                }
+                None => {}
+            }
+        }
+        Ok(Self {
+            deployed_at,
+            holdings: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn holdings(&self, controller: &str, block: u64) -> Result<Holdings, eyre::Error> {
+        let cached = Arc::clone(
+            self.holdings
+                .lock()
+                .map_err(|_| eyre::eyre!("holdings lock poisoned"))?
+                .entry(controller.to_string())
+                .or_default(),
+        );
+        let mut holdings = cached.lock().await;
+        // an older block than we have replayed to means starting over
+        if holdings
+            .scanned_to
+            .is_some_and(|scanned_to| scanned_to > block)
+        {
+            *holdings = Holdings::default();
+        }
+        if holdings.scanned_to != Some(block) {
+            let from = match holdings.scanned_to {
+                Some(scanned_to) => scanned_to + 1,
+                None => *self.deployed_at.get(controller).ok_or(eyre::eyre!(
+                    "controller {} has no deployed_at_block",
+                    controller
+                ))?,
+            };
+            for event in events(controller.parse::<Address>()?, from, block).await? {
+                holdings.apply(event);
+            }
+            holdings.scanned_to = Some(block);
+        }
+        Ok(holdings.clone())
+    }
+}
+
+/// The controller's events that change which vault holds which NFT, in order
+async fn events(
+    controller: Address,
+    from: u64,
+    to: u64,
+) -> Result<Vec<PaprControllerABIEvents>, eyre::Error> {
+    let signatures = vec![
+        AllowCollateralFilter::signature(),
+        AddCollateralFilter::signature(),
+        RemoveCollateralFilter::signature(),
+        StartAuctionFilter::signature(),
+    ];
+    let mut events = vec![];
+    let mut start = from;
+    while start <= to {
+        let end = to.min(start + LOG_RANGE_BLOCKS - 1);
+        let filter = Filter::new()
+            .address(controller)
+            .topic0(signatures.clone())
+            .from_block(start)
+            .to_block(end);
+        for log in PROVIDER.get_logs(&filter).await? {
+            events.push(PaprControllerABIEvents::decode_log(&RawLog {
+                topics: log.topics,
+                data: log.data.to_vec(),
+            })?);
+        }
+        start = end + 1;
+    }
+    Ok(events)
+}
+
+impl VaultSource for ChainVaultSource {
+    async fn snapshot_block(&self, _max_lag_blocks: u64) -> Result<u64, eyre::Error> {
+        latest_block_number().await
+    }
+
+    async fn controller(&self, id: &str, block: u64) -> Result<Controller, eyre::Error> {
+        let contract = PaprControllerABI::new(id.parse::<Address>()?, Arc::clone(&PROVIDER));
+        let underlying = contract.underlying().block(block).call().await?;
+        Ok(Controller {
+            id: id.to_lowercase(),
+            max_ltv: contract.max_ltv().block(block).call().await?,
+            underlying: format!("{:?}", underlying),
+            underlying_decimals: ERC20::new(underlying, Arc::clone(&PROVIDER))
+                .decimals()
+                .call()
+                .await?,
+        })
+    }
+
+    async fn collateral(&self, controller: &str, block: u64) -> Result<Vec<String>, eyre::Error> {
+        Ok(self
+            .holdings(controller, block)
+            .await?
+            .collateral
+            .iter()
+            .map(|collateral| format!("{:?}", collateral))
+            .collect())
+    }
+
+    async fn vaults_exceeding_debt_per_collateral(
+        &self,
+        controller: &str,
+        collateral: &str,
+        debt_per_collateral: U256,
+        latest_auction_before: u64,
+        block: u64,
+    ) -> Result<Vec<Vault>, eyre::Error> {
+        let collateral = collateral.parse::<Address>()?;
+        let contract =
+            PaprControllerABI::new(controller.parse::<Address>()?, Arc::clone(&PROVIDER));
+        let mut vaults = vec![];
+        for (account, token_ids) in self.holdings(controller, block).await?.vaults(collateral) {
+            let info = contract
+                .vault_info(account, collateral)
+                .block(block)
+                .call()
+                .await?;
+            if info.count == 0 || info.latest_auction_start_time >= latest_auction_before {
+                continue;
+            }
+            if info.debt / U256::from(info.count) > debt_per_collateral {
+                vaults.push(Vault {
+                    account,
+                    collateral,
+                    token_ids,
+                });
+            }
+        }
+        Ok(vaults)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        papr_controller::{
+            AddCollateralFilter, AllowCollateralFilter, PaprControllerABIEvents,
+            RemoveCollateralFilter, StartAuctionFilter,
+        },
+        vault_source::chain::{ChainVaultSource, Holdings},
+    };
+    use ethers::types::{Address, U256};
+
+    #[test]
+    fn holdings_follow_collateral_in_and_out_of_vaults() {
+        let collection = Address::from_low_u64_be(1);
+        let other_collection = Address::from_low_u64_be(2);
+        let alice = Address::from_low_u64_be(10);
+        let bob = Address::from_low_u64_be(11);
+        let add = |account, collateral_address, token_id: u64| {
+            PaprControllerABIEvents::AddCollateralFilter(AddCollateralFilter {
+                account,
+                collateral_address,
+                token_id: token_id.into(),
+            })
+        };
+
+        let mut holdings = Holdings::default();
+        for event in [
+            PaprControllerABIEvents::AllowCollateralFilter(AllowCollateralFilter {
+                collateral: collection,
+                is_allowed: true,
+            }),
+            add(alice, collection, 3),
+            add(alice, collection, 1),
+            add(alice, other_collection, 1),
+            add(bob, collection, 2),
+            add(bob, collection, 4),
+            PaprControllerABIEvents::RemoveCollateralFilter(RemoveCollateralFilter {
+                account: bob,
+                collateral_address: collection,
+                token_id: 2.into(),
+            }),
+            PaprControllerABIEvents::StartAuctionFilter(StartAuctionFilter {
+                auction_id: 1.into(),
+                auction_asset_id: 4.into(),
+                auction_asset_contract: collection,
+                nft_owner: bob,
+                per_period_decay_percent_wad: U256::zero(),
+                seconds_in_period: U256::zero(),
+                start_price: U256::zero(),
+                payment_asset: Address::zero(),
+            }),
+        ] {
+            holdings.apply(event);
+        }
+
+        assert_eq!(
+            holdings.collateral.iter().collect::<Vec<_>>(),
+            vec![&collection]
+        );
+        let vaults = holdings.vaults(collection);
+        assert_eq!(vaults.len(), 1);
+        assert_eq!(vaults[&alice], vec![U256::from(1), U256::from(3)]);
+    }
+
+    #[test]
+    fn new_requires_deployed_at_block_of_liquidated_controllers() {
+        let config = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            liquidate = true
+
+            [[controllers]]
+            address = "0xd0a830278773282bbf635fd8e47b2447f1e9fe86"
+            purchase = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            "controller 0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0 needs deployed_at_block to find vaults on chain",
+            ChainVaultSource::new(&config).err().unwrap().to_string()
+        );
+
+        let config = Config::parse(
+            r#"
+            [[controllers]]
+            address = "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0"
+            liquidate = true
+            deployed_at_block = 16000000
+            "#,
+        )
+        .unwrap();
+        assert!(ChainVaultSource::new(&config).is_ok());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::papr_subgraph::queries::ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as SubgraphAuction;
+use clap::ValueEnum;
+use ethers::types::{Address, U256};
+
+pub mod chain;
+pub mod subgraph;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum VaultSourceKind {
+    /// the papr subgraph
+    Subgraph,
+    /// the controllers' events and `vaultInfo` over RPC
+    Chain,
+}
+
+/// A controller's parameters needed to price its vaults' debt.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Controller {
+    /// lowercase address
+    pub id: String,
+    pub max_ltv: U256,
+    /// lowercase address of the asset debt is priced in
+    pub underlying: String,
+    pub underlying_decimals: u8,
+}
+
+/// A vault's collateral of a single collection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vault {
+    pub account: Address,
+    pub collateral: Address,
+    pub token_ids: Vec<U256>,
+}
+
+/// Where liquidation scanning finds controllers and vaults, either the papr
+/// subgraph or the controllers' own events over RPC.
+pub trait VaultSource {
+    /// The block every read of one pass is made at, erroring if the source is
+    /// not fit to act on.
+    async fn snapshot_block(&self, max_lag_blocks: u64) -> Result<u64, eyre::Error>;
+
+    async fn controller(&self, id: &str, block: u64) -> Result<Controller, eyre::Error>;
+
+    /// Every collection the controller has allowed, including collateral since
+    /// disallowed that vaults may still hold.
+    async fn collateral(&self, controller: &str, block: u64) -> Result<Vec<String>, eyre::Error>;
+
+    /// Vaults holding `collateral` with more than `debt_per_collateral` debt per
+    /// token whose latest auction started before `latest_auction_before`.
+    async fn vaults_exceeding_debt_per_collateral(
+        &self,
+        controller: &str,
+        collateral: &str,
+        debt_per_collateral: U256,
+        latest_auction_before: u64,
+        block: u64,
+    ) -> Result<Vec<Vault>, eyre::Error>;
+}
+
+/// Where purchasing finds a controller's ongoing auctions, so it can run
+/// against a fake in tests.
+pub trait AuctionSource: VaultSource {
+    async fn ongoing_auctions(
+        &self,
+        controller: &str,
+        block: u64,
+    ) -> Result<Vec<SubgraphAuction>, eyre::Error>;
+}
@@ -0,0 +1,77 @@
+use crate::{
+    papr_subgraph::{
+        client::GraphQLClient,
+        queries::ongoing_auctions_by_controller::OngoingAuctionsByControllerAuctions as SubgraphAuction,
+    },
+    vault_source::{AuctionSource, Controller, Vault, VaultSource},
+};
+use ethers::types::{Address, U256};
+
+impl VaultSource for GraphQLClient {
+    async fn snapshot_block(&self, max_lag_blocks: u64) -> Result<u64, eyre::Error> {
+        let status = self.indexing_status().await?;
+        status.ensure_healthy(self.chain_head().await?, max_lag_blocks)?;
+        Ok(status.block_number)
+    }
+
+    async fn controller(&self, id: &str, block: u64) -> Result<Controller, eyre::Error> {
+        let controller = GraphQLClient::controller(self, id, Some(block))
+            .await?
+            .ok_or(eyre::eyre!("controller {} not found in subgraph", id))?;
+        Ok(Controller {
+            max_ltv: controller.max_ltv_as_u256()?,
+            id: controller.id,
+            underlying: controller.underlying.id,
+            underlying_decimals: controller.underlying.decimals as u8,
+        })
+    }
+
+    async fn collateral(&self, controller: &str, block: u64) -> Result<Vec<String>, eyre::Error> {
+        Ok(GraphQLClient::collateral(self, controller, Some(block))
+            .await?
+            .into_iter()
+            .map(|collateral| collateral.token.id)
+            .collect())
+    }
+
+    async fn vaults_exceeding_debt_per_collateral(
+        &self,
+        controller: &str,
+        collateral: &str,
+        debt_per_collateral: U256,
+        latest_auction_before: u64,
+        block: u64,
+    ) -> Result<Vec<Vault>, eyre::Error> {
+        self.collateral_vaults_exceeding_debt_per_collateral(
+            controller,
+            collateral,
+            debt_per_collateral,
+            latest_auction_before,
+            Some(block),
+        )
+        .await?
+        .into_iter()
+        .map(|vault| {
+            Ok(Vault {
+                account: vault.account.to_string().parse::<Address>()?,
+                collateral: vault.token.id.parse::<Address>()?,
+                token_ids: vault
+                    .collateral
+                    .iter()
+                    .map(|c| U256::from_dec_str(&c.token_id))
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+    }
+}
+
+impl AuctionSource for GraphQLClient {
+    async fn ongoing_auctions(
+        &self,
+        controller: &str,
+        block: u64,
+    ) -> Result<Vec<SubgraphAuction>, eyre::Error> {
+        GraphQLClient::ongoing_auctions(self, controller, Some(block)).await
+    }
+}
@@ -0,0 +1,161 @@
+//! 18 decimal fixed point math ported from solady's `FixedPointMathLib`, which
+//! the controller uses to price auctions, so results match on chain to the wei.
+use ethers::types::{I256, U256};
+
+pub const WAD: u64 = 1_000_000_000_000_000_000;
+
+fn int(value: &str) -> I256 {
+    I256::from_dec_str(value).expect("valid constant")
+}
+
+pub fn mul_wad_up(x: U256, y: U256) -> U256 {
+    let product = x * y;
+    if product.is_zero() {
+        return product;
+    }
+    (product - 1) / WAD + 1
+}
+
+pub fn mul_wad_down(x: U256, y: U256) -> U256 {
+    x * y / WAD
+}
+
+pub fn div_wad_down(x: U256, y: U256) -> U256 {
+    x * WAD / y
+}
+
+/// `x` to the power of `y`, as `exp(ln(x) * y)`
+pub fn pow_wad(x: I256, y: I256) -> Result<I256, eyre::Error> {
+    exp_wad(ln_wad(x)? * y / I256::from(WAD))
+}
+
+pub fn exp_wad(x: I256) -> Result<I256, eyre::Error> {
+    // the result rounds to zero below ln(0.5e-18)
+    if x <= int("-42139678854452767551") {
+        return Ok(I256::zero());
+    }
+    if x >= int("135305999368893231589") {
+        return Err(eyre::eyre!("exp_wad overflow"));
+    }
+
+    // convert to a 2**96 basis: x * 1e18 / 2**96 = x * 5**18 / 2**78
+    let x = (x << 78u32) / I256::from(5).pow(18);
+
+    // exp(x) = exp(x') * 2**k with x' in (-½ ln 2, ½ ln 2) * 2**96
+    let ln_2 = int("54916777467707473351141471128");
+    let k = ((x << 96u32) / ln_2 + (I256::one() << 95u32)).asr(96);
+    let x = x - k * ln_2;
+
+    // (6, 7)-term rational approximation, p is monic
+    let y = x + int("1346386616545796478920950773328");
+    let y = (y * x).asr(96) + int("57155421227552351082224309758442");
+    let p = y + x - int("94201549194550492254356042504812");
+    let p = (p * y).asr(96) + int("28719021644029726153956944680412240");
+    let p = p * x + (int("4385272521454847904659076985693276") << 96u32);
+
+    let q = x - int("2855989394907223263936484059900");
+    let q = (q * x).asr(96) + int("50020603652535783019961831881945");
+    let q = (q * x).asr(96) - int("533845033583426703283633433725380");
+    let q = (q * x).asr(96) + int("3604857256930695427073651918091429");
+    let q = (q * x).asr(96) - int("14423608567350463180887372962807573");
+    let q = (q * x).asr(96) + int("26449188498355588339934803723976023");
+
+    // p is already 2**96 too large so the quotient needs no scaling
+    let r = (p / q).into_raw();
+
+    // multiply by the scale factor, 2**k and 1e18 / 2**96 at once
+    let shift = (I256::from(195) - k).as_u32();
+    Ok(I256::from_raw(
+        (r * U256::from_dec_str("3822833074963236453042738258902158003155416615667")?) >> shift,
+    ))
+}
+
+pub fn ln_wad(x: I256) -> Result<I256, eyre::Error> {
+    if x <= I256::zero() {
+        return Err(eyre::eyre!("ln_wad undefined for {}", x));
+    }
+
+    // ln(2**k * x) = k * ln(2) + ln(x), reduce x to (1, 2) * 2**96
+    let k = I256::from(x.into_raw().bits() as i64 - 1 - 96);
+    let x = x << (I256::from(159) - k).as_u32();
+    let x = x >> 159u32;
+
+    // (8, 8)-term rational approximation, p is monic
+    let p = x + int("3273285459638523848632254066296");
+    let p = (p * x).asr(96) + int("24828157081833163892658089445524");
+    let p = (p * x).asr(96) + int("43456485725739037958740375743393");
+    let p = (p * x).asr(96) - int("11111509109440967052023855526967");
+    let p = (p * x).asr(96) - int("45023709667254063763336534515857");
+    let p = (p * x).asr(96) - int("14706773417378608786704636184526");
+    let p = p * x - (int("795164235651350426258249787498") << 96u32);
+
+    let q = x + int("5573035233440673466300451813936");
+    let q = (q * x).asr(96) + int("71694874799317883764090561454958");
+    let q = (q * x).asr(96) + int("283447036172924575727196451306956");
+    let q = (q * x).asr(96) + int("401686690394027663651624208769553");
+    let q = (q * x).asr(96) + int("204048457590392012362485061816622");
+    let q = (q * x).asr(96) + int("31853899698501571402653359427138");
+    let q = (q * x).asr(96) + int("909429971244387300277376558375");
+
+    // p is already 2**96 too large so the quotient needs no scaling
+    let r = p / q;
+
+    // multiply by the scale factor, add ln(2**96 / 1e18) and k * ln(2), then
+    // convert back from the 5**18 * 2**192 basis
+    let r = r * int("1677202110996718588342820967067443963516166");
+    let r = r + int("16597577552685614221487285958193947469193820559219878177908093499208371") * k;
+    let r = r + int("600920179829731861736702779321621459595472258049074101567377883020018308");
+    Ok(r.asr(174))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wad::{exp_wad, ln_wad, mul_wad_up, pow_wad, WAD};
+    use ethers::types::{I256, U256};
+
+    fn int(value: &str) -> I256 {
+        I256::from_dec_str(value).unwrap()
+    }
+
+    #[test]
+    fn exp_wad_matches_solidity() {
+        for (x, expected) in [
+            ("-42139678854452767551", "0"),
+            ("-3000000000000000000", "49787068367863942"),
+            ("-1000000000000000000", "367879441171442321"),
+            ("0", "1000000000000000000"),
+            ("1000000000000000000", "2718281828459045235"),
+            ("3000000000000000000", "20085536923187667741"),
+        ] {
+            assert_eq!(int(expected), exp_wad(int(x)).unwrap(), "exp_wad({})", x);
+        }
+        assert!(exp_wad(int("135305999368893231589")).is_err());
+    }
+
+    #[test]
+    fn ln_wad_matches_solidity() {
+        for (x, expected) in [
+            ("1000000000000000000", "0"),
+            ("2718281828459045235", "999999999999999999"),
+            ("11723640096265400935", "2461607324344817918"),
+        ] {
+            assert_eq!(int(expected), ln_wad(int(x)).unwrap(), "ln_wad({})", x);
+        }
+        assert!(ln_wad(I256::zero()).is_err());
+    }
+
+    #[test]
+    fn pow_wad_of_one_is_one() {
+        assert_eq!(
+            I256::from(WAD),
+            pow_wad(I256::from(WAD), int("5000000000000000000")).unwrap()
+        );
+    }
+
+    #[test]
+    fn mul_wad_up_rounds_up() {
+        assert_eq!(U256::zero(), mul_wad_up(U256::zero(), U256::from(WAD)));
+        assert_eq!(U256::one(), mul_wad_up(U256::one(), U256::one()));
+        assert_eq!(U256::from(3), mul_wad_up(U256::from(3), U256::from(WAD)));
+    }
+}
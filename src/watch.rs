@@ -0,0 +1,271 @@
+//! Vaults close to their max debt, for the risk dashboard to keep an eye on
+//! before they become liquidatable.
+use crate::{
+    config::{Config, ControllerConfig},
+    papr_controller::{ControllerExecutor, PaprController},
+    projection::signed_collateral_price,
+    reservoir::oracle::PriceOracle,
+    start::max_debt,
+    vault_source::VaultSource,
+    wad,
+};
+use clap::ValueEnum;
+use ethers::types::{Address, U256};
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// A vault within the watched distance of its max debt
+#[derive(Serialize, Debug, PartialEq)]
+pub struct WatchEntry {
+    pub controller: String,
+    pub account: Address,
+    pub collateral: Address,
+    #[serde(serialize_with = "decimal")]
+    pub debt_per_collateral: U256,
+    #[serde(serialize_with = "decimal")]
+    pub max_debt_per_collateral: U256,
+    /// fraction of the max debt left to borrow before liquidation, negative
+    /// once the vault is liquidatable
+    pub distance: f64,
+}
+
+impl WatchEntry {
+    fn new(
+        controller: &str,
+        account: Address,
+        collateral: Address,
+        debt_per_collateral: U256,
+        max_debt_per_collateral: U256,
+    ) -> Self {
+        // nothing can be borrowed against worthless collateral
+        let distance = if max_debt_per_collateral.is_zero() {
+            f64::NEG_INFINITY
+        } else {
+            let used = wad::div_wad_down(debt_per_collateral, max_debt_per_collateral);
+            1.0 - used.as_u128() as f64 / wad::WAD as f64
+        };
+        Self {
+            controller: controller.to_string(),
+            account,
+            collateral,
+            debt_per_collateral,
+            max_debt_per_collateral,
+            distance,
+        }
+    }
+}
+
+/// U256s as decimal strings, which spreadsheets and dashboards read
+fn decimal<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+/// Vaults within `within` (a fraction, 0.1 for 10%) of their max debt across
+/// the controllers the bot liquidates for, closest to liquidation first.
+pub async fn watch_whitelisted_controllers(
+    oracle: &impl PriceOracle,
+    vaults: &impl VaultSource,
+    config: &Config,
+    within: f64,
+) -> Result<Vec<WatchEntry>, eyre::Error> {
+    let snapshot_block = vaults
+        .snapshot_block(config.max_subgraph_lag_blocks)
+        .await?;
+    let mut entries = vec![];
+    for controller_config in config.liquidation_controllers() {
+        let provider = PaprController::new(&controller_config.address)?;
+        match watch_controller(
+            controller_config,
+            oracle,
+            vaults,
+            &provider,
+            snapshot_block,
+            within,
+        )
+        .await
+        {
+            Ok(found) => entries.extend(found),
+            Err(err) => println!(
+                "error watching controller {}: {}",
+                controller_config.address, err
+            ),
+        }
+    }
+    sort_by_distance(&mut entries);
+    Ok(entries)
+}
+
+/// Vaults of one controller within `within` of their max debt
+pub async fn watch_controller(
+    config: &ControllerConfig,
+    oracle: &impl PriceOracle,
+    vaults: &impl VaultSource,
+    provider: &impl ControllerExecutor,
+    snapshot_block: u64,
+    within: f64,
+) -> Result<Vec<WatchEntry>, eyre::Error> {
+    let controller = vaults.controller(&config.address, snapshot_block).await?;
+    let target = provider.new_target().await?;
+    let oracle_signer = provider.oracle_signer().await?;
+
+    let mut entries = vec![];
+    for collateral in vaults.collateral(&controller.id, snapshot_block).await? {
+        if !config.collateral_enabled(&collateral) {
+            continue;
+        }
+        let price =
+            signed_collateral_price(oracle, &controller, &collateral, config, oracle_signer)
+                .await?;
+        let max = max_debt(price, controller.max_ltv, target)?;
+        let watched = vaults
+            .vaults_exceeding_debt_per_collateral(
+                &controller.id,
+                &collateral,
+                lowered_threshold(max, within)?,
+                u64::MAX,
+                snapshot_block,
+            )
+            .await?;
+        for vault in watched {
+            let info = provider.vault_info(vault.account, vault.collateral).await?;
+            if info.count == 0 {
+                continue;
+            }
+            entries.push(WatchEntry::new(
+                &controller.id,
+                vault.account,
+                vault.collateral,
+                info.debt / U256::from(info.count),
+                max,
+            ));
+        }
+    }
+    sort_by_distance(&mut entries);
+    Ok(entries)
+}
+
+/// `max_debt` lowered by `within`, a fraction between 0 and 1
+fn lowered_threshold(max_debt: U256, within: f64) -> Result<U256, eyre::Error> {
+    if !(0.0..=1.0).contains(&within) {
+        return Err(eyre::eyre!("within {} is not between 0 and 1", within));
+    }
+    let kept = ((1.0 - within) * wad::WAD as f64) as u64;
+    Ok(wad::mul_wad_down(max_debt, kept.into()))
+}
+
+fn sort_by_distance(entries: &mut [WatchEntry]) {
+    entries.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+}
+
+pub fn render(entries: &[WatchEntry], format: ReportFormat) -> Result<String, eyre::Error> {
+    Ok(match format {
+        ReportFormat::Text => entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "{:.2}% from max debt controller {} vault {:?} collateral {:?} debt per collateral {} max {}\n",
+                    e.distance * 100.0,
+                    e.controller,
+                    e.account,
+                    e.collateral,
+                    e.debt_per_collateral,
+                    e.max_debt_per_collateral
+                )
+            })
+            .collect(),
+        ReportFormat::Json => serde_json::to_string_pretty(entries)? + "\n",
+        ReportFormat::Csv => {
+            let mut csv = "controller,account,collateral,debt_per_collateral,max_debt_per_collateral,distance\n"
+                .to_string();
+            for e in entries {
+                csv.push_str(&format!(
+                    "{},{:?},{:?},{},{},{}\n",
+                    e.controller,
+                    e.account,
+                    e.collateral,
+                    e.debt_per_collateral,
+                    e.max_debt_per_collateral,
+                    e.distance
+                ));
+            }
+            csv
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watch::{lowered_threshold, render, sort_by_distance, ReportFormat, WatchEntry};
+    use ethers::types::{Address, U256};
+    use serde_json::json;
+
+    fn entry(account: u64, debt_per_collateral: u64) -> WatchEntry {
+        WatchEntry::new(
+            "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0",
+            Address::from_low_u64_be(account),
+            Address::from_low_u64_be(1),
+            U256::from(debt_per_collateral),
+            U256::from(1000),
+        )
+    }
+
+    #[test]
+    fn lowered_threshold_takes_fraction_off_max_debt() {
+        let max = U256::exp10(18);
+        assert_eq!(lowered_threshold(max, 0.1).unwrap(), U256::exp10(17) * 9);
+        assert_eq!(lowered_threshold(max, 0.0).unwrap(), max);
+        assert!(lowered_threshold(max, 1.5).is_err());
+    }
+
+    #[test]
+    fn entries_sort_closest_to_liquidation_first() {
+        let mut entries = vec![entry(10, 900), entry(11, 1100), entry(12, 950)];
+        sort_by_distance(&mut entries);
+        let accounts: Vec<_> = entries.iter().map(|e| e.account).collect();
+        assert_eq!(
+            accounts,
+            vec![
+                Address::from_low_u64_be(11),
+                Address::from_low_u64_be(12),
+                Address::from_low_u64_be(10)
+            ]
+        );
+        assert!((entries[0].distance + 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn renders_json_and_csv() {
+        let entries = vec![entry(10, 900)];
+        let mut json: serde_json::Value =
+            serde_json::from_str(&render(&entries, ReportFormat::Json).unwrap()).unwrap();
+        let distance = json[0]["distance"].take().as_f64().unwrap();
+        assert!((distance - 0.1).abs() < 1e-9);
+        assert_eq!(
+            json,
+            json!([{
+                "controller": "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0",
+                "account": "0x000000000000000000000000000000000000000a",
+                "collateral": "0x0000000000000000000000000000000000000001",
+                "debt_per_collateral": "900",
+                "max_debt_per_collateral": "1000",
+                "distance": null,
+            }])
+        );
+
+        let csv = render(&entries, ReportFormat::Csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "controller,account,collateral,debt_per_collateral,max_debt_per_collateral,distance"
+        );
+        assert!(lines[1].starts_with(
+            "0x3b29c19ff2fcea0ff98d0ef5b184354d74ea74b0,0x000000000000000000000000000000000000000a,0x0000000000000000000000000000000000000001,900,1000,"
+        ));
+    }
+}